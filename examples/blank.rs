@@ -4,6 +4,8 @@ use nmg::alg;
 use nmg::entity;
 use nmg::render;
 use nmg::components;
+use nmg::input;
+use nmg::control;
 use nmg::debug;
 
 struct Demo { }
@@ -26,6 +28,8 @@ impl nmg::Update for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width:  u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities:   &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -46,6 +50,8 @@ impl nmg::FixedUpdate for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -53,5 +59,8 @@ impl nmg::FixedUpdate for Demo {
 }
 
 fn main() {
-    nmg::go(vec![], Demo { })
+    if let Err(e) = nmg::go(vec![], Demo { }) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -6,6 +6,8 @@ use nmg::render;
 use nmg::graphics;
 use nmg::components;
 use nmg::components::Component;
+use nmg::input;
+use nmg::control;
 use nmg::debug;
 
 /* In debug mode, this demo will render in wireframe, with physics markers.
@@ -78,6 +80,8 @@ impl nmg::Update for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -143,6 +147,8 @@ impl nmg::FixedUpdate for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -156,5 +162,8 @@ fn main() {
         last_target: alg::Vec3::zero(),
     };
 
-    nmg::go(vec![], demo)
+    if let Err(e) = nmg::go(vec![], demo) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -5,6 +5,8 @@ use nmg::entity;
 use nmg::render;
 use nmg::components;
 use nmg::components::Component;
+use nmg::input;
+use nmg::control;
 use nmg::debug;
 
 struct Demo {
@@ -56,6 +58,8 @@ impl nmg::Update for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width:  u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities:   &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -92,6 +96,8 @@ impl nmg::FixedUpdate for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -117,7 +123,10 @@ fn main() {
     };
 
     let model_data = get_models();
-    nmg::go(model_data, demo)
+    if let Err(e) = nmg::go(model_data, demo) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
 
 fn get_models() -> Vec<render::ModelData> {
@@ -6,6 +6,8 @@ use nmg::render;
 use nmg::graphics;
 use nmg::components;
 use nmg::components::Component;
+use nmg::input;
+use nmg::control;
 use nmg::debug;
 
 /* In debug mode, this demo will render in wireframe, with physics markers.
@@ -93,6 +95,8 @@ impl nmg::Update for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -136,6 +140,8 @@ impl nmg::FixedUpdate for Demo {
         metadata: nmg::Metadata,
         screen_height: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -170,7 +176,10 @@ fn main() {
         mesh: mesh,
     };
 
-    nmg::go(model_data, demo)
+    if let Err(e) = nmg::go(model_data, demo) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
 
 fn get_models() -> Vec<render::ModelData> {
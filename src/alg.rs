@@ -16,7 +16,7 @@ fn inverse_sqrt(x: f32) -> f32 {
     iteration * (1.5 - half * iteration * iteration)
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 #[repr(C)]
 pub struct Vec2 {
     pub x: f32,
@@ -44,6 +44,11 @@ impl Vec2 {
         }
     }
 
+    #[inline]
+    pub fn zero() -> Vec2 {
+        Vec2::new(0., 0.)
+    }
+
     #[inline]
     pub fn dot(self, other: Vec2) -> f32 {
         self.x * other.x + self.y * other.y
@@ -63,6 +68,28 @@ impl Vec2 {
     }
 }
 
+impl std::ops::Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl std::ops::Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 #[repr(C)]
 pub struct Vec3 {
@@ -490,14 +517,52 @@ impl Quat {
     }
 
     pub fn from_mat(m: Mat) -> Quat {
-        let w = (1.0 + m.x0 + m.y1 + m.z2).sqrt() * 0.5;
-        let x4 = w * 4.0;
+        let trace = m.x0 + m.y1 + m.z2;
+
+        if trace > 0.0 {
+            let w = (1.0 + trace).sqrt() * 0.5;
+            let x4 = w * 4.0;
+
+            return Quat {
+                x: (m.z1 - m.y2) / x4,
+                y: (m.x2 - m.z0) / x4,
+                z: (m.y0 - m.x1) / x4,
+                w: w,
+            };
+        }
 
-        Quat {
-            x: (m.z1 - m.y2) / x4,
-            y: (m.x2 - m.z0) / x4,
-            z: (m.y0 - m.x1) / x4,
-            w: w,
+        // Rotations near 180 degrees: solve for the largest component
+        // instead of w, which goes to zero
+        if m.x0 > m.y1 && m.x0 > m.z2 {
+            let x = (1.0 + m.x0 - m.y1 - m.z2).sqrt() * 0.5;
+            let x4 = x * 4.0;
+
+            Quat {
+                x,
+                y: (m.x1 + m.y0) / x4,
+                z: (m.x2 + m.z0) / x4,
+                w: (m.z1 - m.y2) / x4,
+            }
+        } else if m.y1 > m.z2 {
+            let y = (1.0 + m.y1 - m.x0 - m.z2).sqrt() * 0.5;
+            let y4 = y * 4.0;
+
+            Quat {
+                x: (m.x1 + m.y0) / y4,
+                y,
+                z: (m.y2 + m.z1) / y4,
+                w: (m.x2 - m.z0) / y4,
+            }
+        } else {
+            let z = (1.0 + m.z2 - m.x0 - m.y1).sqrt() * 0.5;
+            let z4 = z * 4.0;
+
+            Quat {
+                x: (m.x2 + m.z0) / z4,
+                y: (m.y2 + m.z1) / z4,
+                z,
+                w: (m.y0 - m.x1) / z4,
+            }
         }
     }
 
@@ -569,6 +634,22 @@ impl Quat {
         self.mag_squared().sqrt()
     }
 
+    // Normalized linear interpolation, along the shortest path
+    pub fn nlerp(self, other: Quat, t: f32) -> Quat {
+        let dot = self.x * other.x + self.y * other.y
+            + self.z * other.z + self.w * other.w;
+
+        let sign = if dot < 0. { -1. } else { 1. };
+        let (s, o) = (1. - t, t * sign);
+
+        Quat {
+            x: self.x * s + other.x * o,
+            y: self.y * s + other.y * o,
+            z: self.z * s + other.z * o,
+            w: self.w * s + other.w * o,
+        }.norm()
+    }
+
     pub fn conjugate(self) -> Quat {
         Quat {
             x: -self.x,
@@ -869,6 +950,20 @@ mod tests {
         assert!(error < 0.0001);
     }
 
+    #[test]
+    fn convert_mat() {
+        for &angle in &[0.5, 3.0, std::f32::consts::PI, -3.1] {
+            for &axis in &[Vec3::right(), Vec3::up(), Vec3::fwd()] {
+                // Compare rotations (q and -q are the same rotation)
+                let mat = Quat::axis_angle(axis, angle).to_mat();
+                let error = mat_error(Quat::from_mat(mat).to_mat(), mat);
+
+                eprintln!("Error: {}", error);
+                assert!(error < 0.0001);
+            }
+        }
+    }
+
     #[test]
     fn invert_quat() {
         assert!(Quat::id() * Quat::id().conjugate() == Quat::id());
@@ -914,6 +1009,32 @@ mod tests {
         assert!(error < 0.1); // TODO
     }
 
+    #[test]
+    fn nlerp_quat() {
+        let q1 = Quat::axis_angle(Vec3::up(), 0.2);
+        let q2 = Quat::axis_angle(Vec3::up(), 1.0);
+
+        assert!(quat_error(q1.nlerp(q2, 0.), q1) < 0.0001);
+        assert!(quat_error(q1.nlerp(q2, 1.), q2) < 0.0001);
+
+        let error = quat_error(
+            q1.nlerp(q2, 0.5),
+            Quat::axis_angle(Vec3::up(), 0.6),
+        );
+
+        eprintln!("Error: {}", error);
+        assert!(error < 0.0001);
+
+        // Opposite hemisphere should take the short path
+        let error = quat_error(
+            q1.nlerp(q2 * -1., 0.5),
+            Quat::axis_angle(Vec3::up(), 0.6),
+        );
+
+        eprintln!("Error: {}", error);
+        assert!(error < 0.0001);
+    }
+
     #[test]
     fn quat_eq() {
         let q1 = Quat::new(-1.0, -2.0, -3.0, -4.0).norm();
@@ -0,0 +1,237 @@
+use std;
+use config;
+
+use error::Error;
+
+// Time left to the OS scheduler before spinning (seconds)
+const SPIN_MARGIN: f64 = 0.002;
+
+// Source of time for the engine loop
+pub trait Clock {
+    // Seconds since the clock was created
+    fn now(&self) -> f64;
+
+    // Block for the given number of seconds
+    fn sleep(&mut self, seconds: f64);
+}
+
+// Wall clock, used when running with a window
+pub struct RealClock {
+    start: std::time::Instant,
+}
+
+impl RealClock {
+    pub fn new() -> RealClock {
+        RealClock {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> RealClock {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> f64 {
+        let duration = self.start.elapsed();
+
+        duration.as_secs() as f64
+            + (duration.subsec_nanos() as f64 / 1000000000.)
+    }
+
+    fn sleep(&mut self, seconds: f64) {
+        let end = self.now() + seconds;
+
+        // Sleep for the bulk of the duration (OS timers are coarse)...
+        let coarse = seconds - SPIN_MARGIN;
+
+        if coarse > 0. {
+            std::thread::sleep(
+                std::time::Duration::new(
+                    coarse as u64,
+                    (coarse.fract() * 1000000000.) as u32,
+                )
+            );
+        }
+
+        // ...then spin for the remainder
+        while self.now() < end {
+            std::thread::yield_now();
+        }
+    }
+}
+
+// Manually-advanced clock, used when running headless
+pub struct SimulatedClock {
+    time: f64,
+}
+
+impl SimulatedClock {
+    pub fn new() -> SimulatedClock {
+        SimulatedClock {
+            time: 0.,
+        }
+    }
+
+    // Simulate work taking the given number of seconds
+    pub fn advance(&mut self, seconds: f64) {
+        debug_assert!(seconds >= 0.);
+        self.time += seconds;
+    }
+}
+
+impl Default for SimulatedClock {
+    fn default() -> SimulatedClock {
+        SimulatedClock::new()
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> f64 {
+        self.time
+    }
+
+    fn sleep(&mut self, seconds: f64) {
+        self.advance(seconds);
+    }
+}
+
+// Frame rate target, set by "fps" in the config
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameLimit {
+    Target(u32), // Frames per second
+    Unlimited,
+    Vsync, // Paced by presentation instead of the limiter
+}
+
+impl FrameLimit {
+    // Load from the engine config, falling back on vsync
+    pub fn from_config() -> Result<FrameLimit, Error> {
+        Ok(config::get("settings", "fps")?.unwrap_or(FrameLimit::Vsync))
+    }
+}
+
+impl std::str::FromStr for FrameLimit {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<FrameLimit, Self::Err> {
+        match value.trim() {
+            "unlimited" => Ok(FrameLimit::Unlimited),
+            "vsync" => Ok(FrameLimit::Vsync),
+            value => match value.parse()? {
+                0 => Ok(FrameLimit::Unlimited),
+                fps => Ok(FrameLimit::Target(fps)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for FrameLimit {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            FrameLimit::Target(fps) => write!(out, "{}", fps),
+            FrameLimit::Unlimited => write!(out, "unlimited"),
+            FrameLimit::Vsync => write!(out, "vsync"),
+        }
+    }
+}
+
+// Sleeps at the end of each frame to hit the frame rate target
+pub struct Limiter {
+    limit: FrameLimit,
+    next: Option<f64>, // Deadline for the current frame
+}
+
+impl Limiter {
+    pub fn new(limit: FrameLimit) -> Limiter {
+        Limiter {
+            limit,
+            next: None,
+        }
+    }
+
+    pub fn limit(&self) -> FrameLimit {
+        self.limit
+    }
+
+    // Call once per frame, after the frame has been submitted
+    pub fn wait<C: Clock>(&mut self, clock: &mut C) {
+        let period = match self.limit {
+            FrameLimit::Target(fps) => 1. / fps as f64,
+            _ => return,
+        };
+
+        let now = clock.now();
+
+        // The first frame started when the clock did
+        let deadline = self.next.unwrap_or(period);
+
+        if now < deadline {
+            clock.sleep(deadline - now);
+            self.next = Some(deadline + period);
+        } else {
+            // Running behind--don't try to catch up
+            self.next = Some(now + period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clock::*;
+
+    #[test]
+    fn parse_limit() {
+        let parse = |value: &str| value.parse::<FrameLimit>();
+
+        assert!(parse("60") == Ok(FrameLimit::Target(60)));
+        assert!(parse("0") == Ok(FrameLimit::Unlimited));
+        assert!(parse("unlimited") == Ok(FrameLimit::Unlimited));
+        assert!(parse(" vsync") == Ok(FrameLimit::Vsync));
+        assert!(parse("fast").is_err());
+    }
+
+    #[test]
+    fn limit_simulated() {
+        let mut clock = SimulatedClock::new();
+        let mut limiter = Limiter::new(FrameLimit::Target(50));
+
+        for _ in 0..100 {
+            clock.advance(0.001); // Frame work
+            limiter.wait(&mut clock);
+        }
+
+        eprintln!("Time: {}", clock.now());
+        assert!((clock.now() - 2.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn limit_slow_frames() {
+        let mut clock = SimulatedClock::new();
+        let mut limiter = Limiter::new(FrameLimit::Target(50));
+
+        // Frames that take longer than the target are never padded
+        for _ in 0..10 {
+            clock.advance(0.05);
+            limiter.wait(&mut clock);
+        }
+
+        assert!((clock.now() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut clock = SimulatedClock::new();
+        let mut limiter = Limiter::new(FrameLimit::Unlimited);
+
+        for _ in 0..10 {
+            clock.advance(0.001);
+            limiter.wait(&mut clock);
+        }
+
+        assert!((clock.now() - 0.01).abs() < 0.0001);
+    }
+}
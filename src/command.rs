@@ -0,0 +1,295 @@
+use entity;
+use components;
+
+use components::Component;
+
+type Setup = Box<dyn FnOnce(&mut components::Container, entity::Handle)>;
+
+// Queued change to the entities, applied at the next sync point
+enum Command {
+    Spawn(entity::Handle),
+    Despawn(entity::Handle),
+    Remove(entity::Handle, components::Signature),
+    With(entity::Handle, Setup), // Runs only if the entity is still alive
+}
+
+// Entity changes made from the game's callbacks, deferred until the engine
+// applies them (after the fixed update loop, before the draw transfer).
+// Despawning through the buffer is safe while iterating over the game's
+// own handle lists, and keeps the physics state consistent within a step.
+//
+//     let bullet = components.commands.spawn(entities);
+//     components.commands.add(bullet, |components, bullet| {
+//         components.transforms.register(bullet);
+//     });
+pub struct Buffer {
+    commands: Vec<Command>,
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer {
+            commands: Vec::new(),
+        }
+    }
+
+    // The new handle can be used in later commands right away,
+    // but isn't alive until the commands are applied
+    pub fn spawn(&mut self, entities: &mut entity::Manager) -> entity::Handle {
+        let entity = entities.reserve();
+        self.commands.push(Command::Spawn(entity));
+
+        entity
+    }
+
+    // Destroy the entity along with all of its components
+    pub fn despawn(&mut self, entity: entity::Handle) {
+        self.commands.push(Command::Despawn(entity));
+    }
+
+    // Register components (or run any other setup) on the entity
+    pub fn add<F>(&mut self, entity: entity::Handle, f: F)
+    where
+        F: FnOnce(&mut components::Container, entity::Handle) + 'static
+    {
+        self.commands.push(Command::With(entity, Box::new(f)));
+    }
+
+    // Unregister the core components in the signature
+    pub fn remove(
+        &mut self,
+        entity: entity::Handle,
+        signature: components::Signature,
+    ) {
+        self.commands.push(Command::Remove(entity, signature));
+    }
+
+    // Unregister a custom component
+    pub fn remove_custom<T: components::Custom>(
+        &mut self,
+        entity: entity::Handle,
+    ) {
+        self.add(entity, |components, entity| {
+            if let Some(manager) = components.get_mut::<T>() {
+                manager.unregister(entity);
+            }
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // Called by the engine through components::Container::apply_commands()
+    pub fn apply(
+        &mut self,
+        entities: &mut entity::Manager,
+        components: &mut components::Container,
+    ) {
+        for command in self.commands.drain(..) {
+            match command {
+                Command::Spawn(entity) => entities.spawn(entity),
+
+                // Stale handles are ignored
+                Command::Despawn(entity) => {
+                    entities.destroy(entity, components);
+                },
+
+                Command::Remove(entity, signature) => {
+                    if !entities.check(entity) {
+                        continue;
+                    }
+
+                    remove(components, entity, signature);
+                },
+
+                Command::With(entity, f) => {
+                    if entities.check(entity) {
+                        f(components, entity);
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Buffer {
+        Buffer::new()
+    }
+}
+
+fn remove(
+    components: &mut components::Container,
+    entity: entity::Handle,
+    signature: components::Signature,
+) {
+    use components::Signature;
+
+    if signature.contains(Signature::TRANSFORM) {
+        components.transforms.unregister(entity);
+    }
+
+    if signature.contains(Signature::DRAW) {
+        components.draws.unregister(entity);
+    }
+
+    if signature.contains(Signature::RIGIDBODY) {
+        components.rigidbodies.unregister(entity);
+    }
+
+    if signature.contains(Signature::SOFTBODY) {
+        components.softbodies.unregister(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use scene;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+    use command::*;
+
+    #[test]
+    fn deferred() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        let a = entities.add();
+        components.transforms.register(a);
+        components.rigidbodies.register(a);
+
+        let b = components.commands.spawn(&mut entities);
+
+        components.commands.add(b, |components, b| {
+            components.transforms.register(b);
+            components.draws.register(b, 0);
+        });
+
+        components.commands.remove(a, Signature::RIGIDBODY);
+
+        // Nothing happens until the sync point
+        assert!(!entities.check(b));
+        assert!(entities.count() == 1);
+        assert!(components.rigidbodies.has(a));
+        assert!(components.commands.len() == 3);
+
+        components.apply_commands(&mut entities);
+
+        assert!(components.commands.is_empty());
+        assert!(entities.check(b));
+        assert!(entities.count() == 2);
+        assert!(
+            components.signature(b)
+                == Signature::TRANSFORM | Signature::DRAW
+        );
+
+        assert!(components.signature(a) == Signature::TRANSFORM);
+    }
+
+    #[test]
+    fn despawn_while_iterating() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        // A chain of jointed limbs
+        let limbs: Vec<entity::Handle> = (0..4).map(|_| {
+            let limb = entities.add();
+            components.transforms.register(limb);
+            components.softbodies.register(limb);
+            components.softbodies.init_limb(limb, 1., 1., alg::Vec3::one());
+            limb
+        }).collect();
+
+        for pair in limbs.windows(2) {
+            components.softbodies.add_joint(
+                pair[0],
+                pair[1],
+                (-30., 30.),
+                (-30., 30.),
+                (-30., 30.),
+            );
+        }
+
+        for (i, limb) in limbs.iter().enumerate() {
+            if i % 2 == 0 {
+                components.commands.despawn(*limb);
+            }
+
+            // Still intact
+            assert!(components.softbodies.has(*limb));
+        }
+
+        // Queued twice; the second is a no-op
+        components.commands.despawn(limbs[0]);
+
+        components.apply_commands(&mut entities);
+        components.softbodies.simulate(&mut components.transforms);
+
+        let signature = Signature::TRANSFORM | Signature::SOFTBODY;
+        assert!(entities.count() == 2);
+        assert!(
+            entities.query(&components, signature).collect::<Vec<_>>()
+                == vec![limbs[1], limbs[3]]
+        );
+    }
+
+    #[test]
+    fn spawn_then_despawn() {
+        let mut entities = entity::Manager::new(2);
+        let mut components = container();
+
+        let a = components.commands.spawn(&mut entities);
+        components.commands.add(a, |components, a| {
+            components.transforms.register(a);
+        });
+
+        components.commands.despawn(a);
+
+        // Never set up, since it's already gone
+        components.commands.add(a, |components, a| {
+            components.draws.register(a, 0);
+        });
+
+        components.apply_commands(&mut entities);
+
+        assert!(!entities.check(a));
+        assert!(entities.count() == 0);
+        assert!(components.transforms.count() == 0);
+        assert!(components.draws.count() == 0);
+
+        // The index is recycled
+        let b = entities.add();
+        assert!(b.get_index() == a.get_index());
+    }
+
+    #[test]
+    fn dropped_spawn() {
+        let mut entities = entity::Manager::new(2);
+        let mut components = container();
+
+        let a = components.commands.spawn(&mut entities);
+        components.commands.add(a, |components, a| {
+            components.transforms.register(a);
+        });
+
+        // Discarded before the sync point
+        components.commands = Buffer::new();
+        assert!(scene::to_string(&entities, &components, &[]).is_err());
+
+        components.apply_commands(&mut entities);
+        assert!(scene::to_string(&entities, &components, &[]).is_ok());
+
+        // The index is recycled, without bringing back the old handle
+        let b = entities.add();
+        assert!(b.get_index() == a.get_index());
+        assert!(!entities.check(a));
+        assert!(components.transforms.count() == 0);
+    }
+}
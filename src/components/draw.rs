@@ -1,8 +1,9 @@
 use std;
-use alg;
+use scene;
 use entity;
 use render;
 
+use components::Component;
 use components::transform;
 use components::softbody;
 
@@ -12,12 +13,24 @@ pub struct Manager {
         entity::Handle,
         render::InstanceHandle,
     >,
+
+    // Reverse lookup, for fixing up handles when instances move
+    owners: std::collections::HashMap<
+        render::InstanceHandle,
+        entity::Handle,
+    >,
+
+    // Instances to rewrite on the next transfer even if the transform
+    // hasn't moved (new, or deformed by a softbody last time)
+    pending: std::collections::HashSet<entity::Handle>,
 }
 
 impl Manager {
     pub fn new(hint: usize, instances: render::Instances) -> Manager {
         Manager {
             handles: std::collections::HashMap::with_capacity(hint),
+            owners: std::collections::HashMap::with_capacity(hint),
+            pending: std::collections::HashSet::with_capacity(hint),
             instances: instances,
         }
     }
@@ -27,6 +40,9 @@ impl Manager {
         entity: entity::Handle,
         model_index: usize,
     ) {
+        // Registering again replaces the model
+        self.unregister(entity);
+
         let handle = self.instances.add(
             render::InstanceUBO::default(),
             model_index,
@@ -36,25 +52,63 @@ impl Manager {
             entity,
             handle,
         );
+
+        self.owners.insert(handle, entity);
+        self.pending.insert(entity);
     }
 
-    // Update
+    // Stop drawing the entity
+    pub fn unregister(&mut self, entity: entity::Handle) {
+        let handle = match self.handles.remove(&entity) {
+            Some(handle) => handle,
+            None => return,
+        };
+
+        self.owners.remove(&handle);
+        self.pending.remove(&entity);
+
+        // Another instance took the removed one's place
+        if let Some(moved) = self.instances.remove(handle) {
+            let owner = self.owners.remove(&moved)
+                .expect("Instance without an owner");
+
+            self.handles.insert(owner, handle);
+            self.owners.insert(handle, owner);
+        }
+    }
+
+    // Update; only instances that changed since the last call are
+    // rewritten, so static entities cost nothing
     pub fn transfer(
         &mut self,
-        transforms: &transform::Manager,
+        transforms: &mut transform::Manager,
         softbodies: &softbody::Manager,
+        alpha:      f32, // Interpolation factor between fixed steps
     ) {
+        transforms.propagate(alpha);
+
         for (entity, instance) in &self.handles {
-            // Get transform component data
-            let transform = transforms.get(*entity);
+            // Skip removed entities whose transform has been reclaimed
+            if !transforms.has(*entity) {
+                continue;
+            }
+
+            // Softbody offsets change every step. Keep deformed instances
+            // pending, so that they are reset if the softbody is removed.
+            let deformed = softbodies.has(*entity);
+            let pending = self.pending.remove(entity);
+
+            if deformed {
+                self.pending.insert(*entity);
+            }
+
+            if !pending && !deformed && !transforms.moved(*entity) {
+                continue;
+            }
 
             // Build uniform buffer object
             let ubo = {
-                let translation = alg::Mat::translation_vec(transform.0);
-                let rotation = transform.1.to_mat();
-                let scale = alg::Mat::scale_vec(transform.2);
-
-                let model = translation * rotation * scale;
+                let model = transforms.get_drawn_matrix(*entity);
                 let offsets = softbodies.get_offsets(*entity);
 
                 render::InstanceUBO::new(model, offsets)
@@ -65,7 +119,127 @@ impl Manager {
         }
     }
 
+    pub fn has(&self, entity: entity::Handle) -> bool {
+        self.handles.contains_key(&entity)
+    }
+
     pub fn count(&self) -> usize {
         self.handles.len()
     }
+
+    /* Scenes */
+
+    // Written in instance order, so that loading reproduces it
+    pub fn save_scene(&self, out: &mut scene::Writer) {
+        let mut draws: Vec<_> = self.handles.iter().collect();
+
+        draws.sort_by_key(|&(_, instance)| {
+            (instance.model_index(), instance.instance_index())
+        });
+
+        for (entity, instance) in draws {
+            out.push(
+                scene::Line::new("draw")
+                    .handle(*entity)
+                    .usize(instance.model_index() as usize)
+            );
+        }
+    }
+
+    // Returns false for records belonging to other managers
+    pub fn load_scene(
+        &mut self,
+        kind: &str,
+        fields: &mut scene::Fields,
+    ) -> Result<bool, String> {
+        if kind != "draw" {
+            return Ok(false);
+        }
+
+        let entity = fields.handle()?;
+        let model_index: usize = fields.parse()?;
+
+        if self.has(entity) {
+            return Err(format!("duplicate draw {}", entity));
+        }
+
+        if model_index >= self.instances.model_count() {
+            return Err(format!("no model {}", model_index));
+        }
+
+        self.register(entity, model_index);
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+
+    #[test]
+    fn transfer_changes() {
+        let mut entities = entity::Manager::new(2);
+        let mut components = container();
+
+        let a = entities.add();
+        let b = entities.add();
+
+        for &entity in &[a, b] {
+            components.transforms.register(entity);
+            components.draws.register(entity, 0);
+        }
+
+        drawn(&mut components);
+        let version = components.draws.instances.version();
+
+        // Nothing changed
+        drawn(&mut components);
+        assert!(components.draws.instances.version() == version);
+        assert!(!components.transforms.moved(a));
+
+        // Only the moved entity is rewritten
+        components.transforms.set(
+            b,
+            alg::Vec3::one(),
+            alg::Quat::id(),
+            alg::Vec3::one(),
+        );
+
+        assert!(
+            drawn(&mut components) == vec![
+                alg::Mat::id(),
+                alg::Mat::translation(1., 1., 1.),
+            ]
+        );
+
+        assert!(components.draws.instances.version() == version + 1);
+        assert!(components.transforms.moved(b));
+        assert!(!components.transforms.moved(a));
+
+        // Moving during a fixed step is redrawn until it comes to rest
+        components.transforms.save_fixed();
+        components.transforms.set_position_i(
+            a.get_index() as usize,
+            alg::Vec3::up(),
+        );
+
+        components.transforms.propagate(0.5);
+        components.transforms.propagate(0.75);
+        assert!(components.transforms.moved(a));
+
+        components.transforms.save_fixed();
+        components.transforms.propagate(0.25);
+        assert!(components.transforms.moved(a));
+        assert!(
+            components.transforms.get_drawn_matrix(a)
+                == alg::Mat::translation(0., 1., 0.)
+        );
+
+        components.transforms.propagate(0.5);
+        assert!(!components.transforms.moved(a));
+    }
 }
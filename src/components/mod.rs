@@ -3,16 +3,644 @@ pub mod draw;
 pub mod rigidbody;
 pub mod softbody;
 
+use std;
 use entity;
+use command;
 
 pub trait Component {
+    // Indices of removed entities are reused, and their data may still be
+    // in place (they needn't have been unregistered), so newly registered
+    // entities must start from a clean state. Registering again is a no-op.
     fn register(&mut self, entity: entity::Handle);
+
+    // Ignores entities without the component (and stale handles)
+    fn unregister(&mut self, entity: entity::Handle);
+
+    // False for stale handles
+    fn has(&self, entity: entity::Handle) -> bool;
+
+    // Number of registered entities
     fn count(&self) -> usize;
 }
 
+// Downcasting support for user managers (implemented for every type)
+pub trait AsAny: std::any::Any {
+    fn as_any(&self) -> &dyn std::any::Any;
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+}
+
+impl<T: std::any::Any> AsAny for T {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+// User-defined component manager (e.g. health, AI), added to the container
+// from Start::start. Custom managers are unregistered along with the core
+// components when an entity is destroyed.
+pub trait Custom: Component + AsAny {
+    // Called every fixed step, at the stage the manager was added with.
+    // Other managers can be reached through the container (but not this
+    // one, which is taken out for the duration of the call).
+    #[allow(unused_variables)]
+    fn fixed_update(
+        &mut self,
+        entities:   &entity::Manager,
+        components: &mut Container,
+        delta:      f32,
+    ) { }
+}
+
+// When custom managers are updated within a fixed step, relative to the
+// built-in physics. Managers within a stage run in the order they were added.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    BeforePhysics,    // After the game's fixed update
+    AfterRigidbodies, // Before softbodies
+    AfterPhysics,
+}
+
+struct Entry {
+    stage: Stage,
+    manager: Option<Box<dyn Custom>>, // None while it is being updated
+}
+
 pub struct Container {
     pub transforms:  transform::Manager,
     pub draws:       draw::Manager,
     pub rigidbodies: rigidbody::Manager,
     pub softbodies:  softbody::Manager,
+
+    // Entity changes to make at the next sync point
+    pub commands: command::Buffer,
+
+    custom: Vec<Entry>,
+}
+
+// Set of core components, combined with |
+//
+//     entities.query(&components, Signature::TRANSFORM | Signature::SOFTBODY)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Signature(u8);
+
+impl Signature {
+    pub const TRANSFORM: Signature = Signature(1 << 0);
+    pub const DRAW:      Signature = Signature(1 << 1);
+    pub const RIGIDBODY: Signature = Signature(1 << 2);
+    pub const SOFTBODY:  Signature = Signature(1 << 3);
+
+    pub fn empty() -> Signature {
+        Signature(0)
+    }
+
+    pub fn contains(self, other: Signature) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Signature {
+    type Output = Signature;
+
+    fn bitor(self, other: Signature) -> Signature {
+        Signature(self.0 | other.0)
+    }
+}
+
+impl Default for Signature {
+    fn default() -> Signature {
+        Signature::empty()
+    }
+}
+
+impl Container {
+    pub fn new(
+        transforms:  transform::Manager,
+        draws:       draw::Manager,
+        rigidbodies: rigidbody::Manager,
+        softbodies:  softbody::Manager,
+    ) -> Container {
+        Container {
+            transforms,
+            draws,
+            rigidbodies,
+            softbodies,
+            commands: command::Buffer::new(),
+            custom: Vec::new(),
+        }
+    }
+
+    // Add a user-defined manager (at most one of each type)
+    pub fn add<T: Custom>(&mut self, manager: T, stage: Stage) {
+        assert!(
+            self.get::<T>().is_none(),
+            "Custom manager added twice",
+        );
+
+        self.custom.push(
+            Entry {
+                stage,
+                manager: Some(Box::new(manager)),
+            }
+        );
+    }
+
+    pub fn get<T: Custom>(&self) -> Option<&T> {
+        self.custom.iter()
+            .filter_map(|entry| entry.manager.as_ref())
+            .filter_map(|manager| (**manager).as_any().downcast_ref())
+            .next()
+    }
+
+    pub fn get_mut<T: Custom>(&mut self) -> Option<&mut T> {
+        self.custom.iter_mut()
+            .filter_map(|entry| entry.manager.as_mut())
+            .filter_map(|manager| (**manager).as_any_mut().downcast_mut())
+            .next()
+    }
+
+    // Core components registered to the entity
+    pub fn signature(&self, entity: entity::Handle) -> Signature {
+        let mut signature = Signature::empty();
+
+        if self.transforms.has(entity) {
+            signature = signature | Signature::TRANSFORM;
+        }
+
+        if self.draws.has(entity) {
+            signature = signature | Signature::DRAW;
+        }
+
+        if self.rigidbodies.has(entity) {
+            signature = signature | Signature::RIGIDBODY;
+        }
+
+        if self.softbodies.has(entity) {
+            signature = signature | Signature::SOFTBODY;
+        }
+
+        signature
+    }
+
+    // Whether the entity has every component in the signature
+    pub fn matches(
+        &self,
+        entity: entity::Handle,
+        signature: Signature,
+    ) -> bool {
+        // Only look up the required components
+        (!signature.contains(Signature::TRANSFORM)
+            || self.transforms.has(entity))
+        && (!signature.contains(Signature::DRAW)
+            || self.draws.has(entity))
+        && (!signature.contains(Signature::RIGIDBODY)
+            || self.rigidbodies.has(entity))
+        && (!signature.contains(Signature::SOFTBODY)
+            || self.softbodies.has(entity))
+    }
+
+    // Live entities with every component in the signature, in index order.
+    // Doesn't allocate; safe to call every frame.
+    pub fn query<'a>(
+        &'a self,
+        entities: &'a entity::Manager,
+        signature: Signature,
+    ) -> impl Iterator<Item = entity::Handle> + 'a {
+        entities.iter()
+            .filter(move |&entity| self.matches(entity, signature))
+    }
+
+    // Remove every component of the entity, including custom ones
+    pub fn unregister(&mut self, entity: entity::Handle) {
+        self.transforms.unregister(entity);
+        self.draws.unregister(entity);
+        self.rigidbodies.unregister(entity);
+        self.softbodies.unregister(entity);
+
+        for entry in &mut self.custom {
+            if let Some(ref mut manager) = entry.manager {
+                manager.unregister(entity);
+            }
+        }
+    }
+
+    /* Engine hooks */
+
+    // Apply the queued commands, in order. Commands queued while applying
+    // are left for the next sync point.
+    pub fn apply_commands(&mut self, entities: &mut entity::Manager) {
+        let mut commands = std::mem::take(&mut self.commands);
+
+        commands.apply(entities, self);
+
+        // Nothing can reserve while applying, so any handle still reserved
+        // was spawned by commands that were dropped
+        entities.release_reserved();
+    }
+
+    // Run the fixed update of every custom manager in the stage
+    pub fn fixed_update(
+        &mut self,
+        stage:    Stage,
+        entities: &entity::Manager,
+        delta:    f32,
+    ) {
+        // Managers added during the loop are appended, and wait for
+        // the next step
+        for i in 0..self.custom.len() {
+            if self.custom[i].stage != stage {
+                continue;
+            }
+
+            if let Some(mut manager) = self.custom[i].manager.take() {
+                manager.fixed_update(entities, self, delta);
+                self.custom[i].manager = Some(manager);
+            }
+        }
+    }
+}
+
+// Handle registered at each index, used by the managers to reject stale
+// handles (from removed entities whose index has since been reused)
+pub struct Registry {
+    handles: Vec<Option<entity::Handle>>,
+    count: usize,
+}
+
+impl Registry {
+    pub fn new(hint: usize) -> Registry {
+        Registry {
+            handles: Vec::with_capacity(hint),
+            count: 0,
+        }
+    }
+
+    // Claim the handle's index, returning true if it was newly claimed
+    // (in which case the data at the index should be reset)
+    pub fn register(&mut self, entity: entity::Handle) -> bool {
+        if self.check(entity) {
+            return false;
+        }
+
+        let i = entity.get_index() as usize;
+
+        while i >= self.handles.len() {
+            self.handles.push(None);
+        }
+
+        // Replaces the stale handle, if any
+        if self.handles[i].is_none() {
+            self.count += 1;
+        }
+
+        self.handles[i] = Some(entity);
+        true
+    }
+
+    // Release the handle's index, returning false if it wasn't registered
+    pub fn unregister(&mut self, entity: entity::Handle) -> bool {
+        if !self.check(entity) {
+            return false;
+        }
+
+        self.handles[entity.get_index() as usize] = None;
+        self.count -= 1;
+        true
+    }
+
+    pub fn check(&self, entity: entity::Handle) -> bool {
+        let i = entity.get_index() as usize;
+        self.handles.get(i) == Some(&Some(entity))
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // Registered handles, in index order
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = entity::Handle> + 'a {
+        self.handles.iter().filter_map(|handle| *handle)
+    }
+
+    // Index of the handle's data, panicking if the handle is stale
+    // or was never registered
+    pub fn index(&self, entity: entity::Handle) -> usize {
+        assert!(
+            self.check(entity),
+            "Stale or unregistered entity handle {}",
+            entity,
+        );
+
+        entity.get_index() as usize
+    }
+}
+
+// Shared by the tests across the crate
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use alg;
+    use render;
+    use render::Renderer;
+    use components::*;
+
+    // Two models
+    pub fn container() -> Container {
+        Container::new(
+            transform::Manager::new(8),
+            draw::Manager::new(8, render::Instances::new(2, None)),
+            rigidbody::Manager::new(8),
+            softbody::Manager::new(8, 2, 1),
+        )
+    }
+
+    // Model matrices of the drawn instances of the first model, in instance
+    // order
+    pub fn drawn(components: &mut Container) -> Vec<alg::Mat> {
+        components.draws.transfer(
+            &mut components.transforms,
+            &components.softbodies,
+            1.,
+        );
+
+        let mut recorder = render::Recorder::new(1, 1);
+        let shared = render::SharedUBO::new(alg::Mat::id(), alg::Mat::id());
+        let instances = &components.draws.instances;
+
+        recorder.update(instances, shared).unwrap();
+        recorder.draw(instances).unwrap();
+
+        recorder.last().unwrap().instances[0].iter()
+            .map(|instance| instance.model())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std;
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+
+    // Example user manager
+    struct Health {
+        values: std::collections::HashMap<entity::Handle, f32>,
+        poison: f32, // Per second
+    }
+
+    impl Component for Health {
+        fn register(&mut self, entity: entity::Handle) {
+            self.values.insert(entity, 1.);
+        }
+
+        fn unregister(&mut self, entity: entity::Handle) {
+            self.values.remove(&entity);
+        }
+
+        fn has(&self, entity: entity::Handle) -> bool {
+            self.values.contains_key(&entity)
+        }
+
+        fn count(&self) -> usize {
+            self.values.len()
+        }
+    }
+
+    impl Custom for Health {
+        fn fixed_update(
+            &mut self,
+            entities:   &entity::Manager,
+            components: &mut Container,
+            delta:      f32,
+        ) {
+            // Only entities on the ground are poisoned
+            for (entity, value) in &mut self.values {
+                let grounded = entities.check(*entity)
+                    && components.transforms.has(*entity)
+                    && components.transforms.get_position(*entity).y <= 0.;
+
+                if grounded {
+                    *value -= self.poison * delta;
+                }
+            }
+        }
+    }
+
+    struct Marker;
+
+    impl Component for Marker {
+        fn register(&mut self, _: entity::Handle) { }
+        fn unregister(&mut self, _: entity::Handle) { }
+        fn has(&self, _: entity::Handle) -> bool { false }
+        fn count(&self) -> usize { 0 }
+    }
+
+    impl Custom for Marker { }
+
+
+
+    #[test]
+    fn membership() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        let a = entities.add();
+        let b = entities.add();
+        let c = entities.add();
+
+        // Sparse registration
+        components.transforms.register(c);
+        components.rigidbodies.register(c);
+        components.rigidbodies.register(c);
+        components.softbodies.register(b);
+
+        assert!(components.transforms.count() == 1);
+        assert!(components.rigidbodies.count() == 1);
+        assert!(components.rigidbodies.has(c));
+        assert!(!components.rigidbodies.has(a));
+        assert!(!components.rigidbodies.has(b));
+
+        components.softbodies.unregister(b);
+        components.softbodies.unregister(b);
+        assert!(components.softbodies.count() == 0);
+        assert!(!components.softbodies.has(b));
+
+        // Stale handles don't count as members
+        entities.remove(c);
+        let d = entities.add();
+        components.transforms.register(d);
+        assert!(components.transforms.count() == 1);
+        assert!(components.transforms.has(d));
+        assert!(!components.transforms.has(c));
+
+        components.draws.register(a, 0);
+        assert!(components.draws.has(a));
+        assert!(!components.draws.has(d));
+    }
+
+    #[test]
+    fn query() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        let a = entities.add();
+        let b = entities.add();
+        let c = entities.add();
+        let d = entities.add();
+
+        for &entity in &[a, b, c] {
+            components.transforms.register(entity);
+        }
+
+        components.softbodies.register(b);
+        components.softbodies.register(c);
+        components.rigidbodies.register(d);
+
+        let bodies = Signature::TRANSFORM | Signature::SOFTBODY;
+        let query = |entities: &entity::Manager, components: &Container| {
+            entities.query(components, bodies).collect::<Vec<_>>()
+        };
+
+        assert!(query(&entities, &components) == vec![b, c]);
+        assert!(components.signature(b) == bodies);
+        assert!(components.signature(d) == Signature::RIGIDBODY);
+
+        // Everything matches the empty signature
+        assert!(
+            components.query(&entities, Signature::empty()).count() == 4
+        );
+
+        // Destroyed entities drop out
+        entities.destroy(b, &mut components);
+        assert!(query(&entities, &components) == vec![c]);
+
+        // Removed entities drop out even if their components don't
+        entities.remove(c);
+        assert!(query(&entities, &components).is_empty());
+    }
+
+
+
+
+
+    #[test]
+    fn custom_managers() {
+        let mut entities = entity::Manager::new(2);
+        let mut components = container();
+
+        components.add(
+            Health {
+                values: std::collections::HashMap::new(),
+                poison: 0.5,
+            },
+            Stage::AfterPhysics,
+        );
+
+        components.add(Marker, Stage::BeforePhysics);
+        assert!(components.get::<Marker>().is_some());
+
+        let grounded = entities.add();
+        let flying = entities.add();
+
+        for &entity in &[grounded, flying] {
+            components.transforms.register(entity);
+            components.get_mut::<Health>().unwrap().register(entity);
+        }
+
+        components.transforms.set(
+            flying,
+            alg::Vec3::up(),
+            alg::Quat::id(),
+            alg::Vec3::one(),
+        );
+
+        // Runs only in its own stage
+        components.fixed_update(Stage::BeforePhysics, &entities, 1.);
+        components.fixed_update(Stage::AfterPhysics, &entities, 1.);
+
+        {
+            let health = components.get::<Health>().unwrap();
+            assert!(health.values[&grounded] == 0.5);
+            assert!(health.values[&flying] == 1.);
+        }
+
+        // Destroying the entity cleans up custom components too
+        entities.destroy(grounded, &mut components);
+        assert!(components.get::<Health>().unwrap().count() == 1);
+        assert!(!components.get::<Health>().unwrap().has(grounded));
+    }
+
+    #[test]
+    #[should_panic]
+    fn custom_twice() {
+        let mut components = container();
+
+        components.add(Marker, Stage::BeforePhysics);
+        components.add(Marker, Stage::AfterPhysics);
+    }
+
+
+
+    #[test]
+    fn destroy() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        let objects: Vec<entity::Handle> = (0..3).map(|i| {
+            let object = entities.add();
+            components.transforms.register(object);
+            components.draws.register(object, 0);
+            components.softbodies.register(object);
+            components.softbodies.init_limb(object, 1., 1., alg::Vec3::one());
+
+            components.transforms.set(
+                object,
+                alg::Vec3::new(i as f32, 0., 0.),
+                alg::Quat::id(),
+                alg::Vec3::one(),
+            );
+
+            object
+        }).collect();
+
+        components.softbodies.add_joint(
+            objects[0],
+            objects[1],
+            (-30., 30.),
+            (-30., 30.),
+            (-30., 30.),
+        );
+
+        entities.destroy(objects[0], &mut components);
+
+        assert!(!entities.check(objects[0]));
+        assert!(!components.transforms.has(objects[0]));
+        assert!(components.draws.count() == 2);
+        assert!(components.draws.instances.count() == 2);
+
+        // The last instance moved into the removed slot
+        assert!(
+            drawn(&mut components) == vec![
+                alg::Mat::translation(2., 0., 0.),
+                alg::Mat::translation(1., 0., 0.),
+            ]
+        );
+
+        // A new entity takes the index without inheriting the joint
+        let object = entities.add();
+        assert!(object.get_index() == objects[0].get_index());
+        components.transforms.register(object);
+        components.softbodies.register(object);
+        components.softbodies.simulate(&mut components.transforms);
+
+        // Destroying again is a no-op
+        entities.destroy(objects[0], &mut components);
+        assert!(components.draws.count() == 2);
+        assert!(entities.count() == 3);
+    }
+
 }
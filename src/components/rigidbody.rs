@@ -1,8 +1,9 @@
 use alg;
+use scene;
 use entity;
 use components;
 
-use ::FIXED_DT; // Import from lib
+use components::Component;
 use components::transform;
 
 // Data layout assumes many physics objects (but may still be sparse)
@@ -14,6 +15,8 @@ pub struct Manager {
     lin_velocities: Vec<alg::Vec3>,
     torques: Vec<alg::Vec3>,
     ang_velocities: Vec<alg::Vec3>,
+
+    registry: components::Registry,
 }
 
 impl components::Component for Manager {
@@ -24,6 +27,10 @@ impl components::Component for Manager {
         debug_assert!(self.lin_velocities.len() == self.torques.len());
         debug_assert!(self.torques.len() == self.ang_velocities.len());
 
+        if !self.registry.register(entity) {
+            return;
+        }
+
         let i = entity.get_index() as usize;
 
         // Resize array to fit new entity
@@ -41,12 +48,26 @@ impl components::Component for Manager {
 
             break;
         }
+
+        // At rest, and uninitialized (massless)
+        self.forces[i] = alg::Vec3::zero();
+        self.masses[i] = 0.;
+        self.drags[i] = 0.;
+        self.lin_velocities[i] = alg::Vec3::zero();
+        self.torques[i] = alg::Vec3::zero();
+        self.ang_velocities[i] = alg::Vec3::zero();
+    }
+
+    fn unregister(&mut self, entity: entity::Handle) {
+        self.registry.unregister(entity);
+    }
+
+    fn has(&self, entity: entity::Handle) -> bool {
+        self.registry.check(entity)
     }
 
-    // TODO: This currently only returns the length of the underlying data
-    // structure, not the count of the registered entities
     fn count(&self) -> usize {
-        self.forces.len()
+        self.registry.count()
     }
 }
 
@@ -59,9 +80,24 @@ impl Manager {
             lin_velocities: Vec::with_capacity(hint),
             torques: Vec::with_capacity(hint),
             ang_velocities: Vec::with_capacity(hint),
+            registry: components::Registry::new(hint),
         }
     }
 
+    // Prepare a registered rigidbody for simulation, at rest
+    // (it is skipped until initialized here or with set)
+    pub fn init(&mut self, entity: entity::Handle, mass: f32, drag: f32) {
+        validate(mass, drag);
+        let i = self.registry.index(entity);
+
+        self.forces[i] = alg::Vec3::zero();
+        self.masses[i] = mass;
+        self.drags[i] = drag;
+        self.lin_velocities[i] = alg::Vec3::zero();
+        self.torques[i] = alg::Vec3::zero();
+        self.ang_velocities[i] = alg::Vec3::zero();
+    }
+
     pub fn set(
         &mut self,
         entity: entity::Handle,
@@ -70,8 +106,8 @@ impl Manager {
         force:  alg::Vec3,
         torque: alg::Vec3,
     ) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.masses.len());
+        validate(mass, drag);
+        let i = self.registry.index(entity);
 
         self.forces[i] = force;
         self.masses[i] = mass;
@@ -79,7 +115,21 @@ impl Manager {
         self.torques[i] = torque;
     }
 
-    pub fn simulate(&mut self, transforms: &mut transform::Manager) {
+    pub fn set_force(&mut self, entity: entity::Handle, force: alg::Vec3) {
+        let i = self.registry.index(entity);
+        self.forces[i] = force;
+    }
+
+    pub fn set_torque(&mut self, entity: entity::Handle, torque: alg::Vec3) {
+        let i = self.registry.index(entity);
+        self.torques[i] = torque;
+    }
+
+    pub fn simulate(
+        &mut self,
+        transforms: &mut transform::Manager,
+        delta: f32, // Fixed timestep
+    ) {
         debug_assert!(self.forces.len() == self.masses.len());
         debug_assert!(self.masses.len() == self.drags.len());
         debug_assert!(self.drags.len() == self.lin_velocities.len());
@@ -87,22 +137,32 @@ impl Manager {
         debug_assert!(self.torques.len() == self.ang_velocities.len());
 
         // Semi-implicit Euler
-        for i in 0..self.forces.len() {
+        for entity in self.registry.iter() {
+            let i = entity.get_index() as usize;
+
+            // Uninitialized (mass is validated, so never zero otherwise)
+            if self.masses[i] <= 0. {
+                continue;
+            }
+
+            // Nothing to move
+            if !transforms.has(entity) {
+                continue;
+            }
+
             /* Linear motion */
 
             // Simple drag
             let lin_resistance = self.lin_velocities[i] * self.drags[i];
 
             let lin_momentum = (self.forces[i] - lin_resistance)
-                * FIXED_DT as f32;
-
-            assert!(self.masses[i] > 0.);
+                * delta;
 
             self.lin_velocities[i] = self.lin_velocities[i]
                 + lin_momentum / self.masses[i];
 
             let position = transforms.get_position_i(i)
-                + self.lin_velocities[i] * FIXED_DT as f32;
+                + self.lin_velocities[i] * delta;
 
             transforms.set_position_i(i, position);
 
@@ -112,7 +172,7 @@ impl Manager {
             let ang_resistance = self.ang_velocities[i] * self.drags[i];
 
             let ang_momentum = (self.torques[i] - ang_resistance)
-                * FIXED_DT as f32;
+                * delta;
 
             // TODO: Tensor support
             let inverse_inertia = 6. / self.masses[i];
@@ -129,9 +189,134 @@ impl Manager {
             );
 
             let last = transforms.get_orientation_i(i).norm(); // Renormalize
-            let orientation = last + last * 0.5 * derivative * FIXED_DT as f32;
+            let orientation = last + last * 0.5 * derivative * delta;
 
             transforms.set_orientation_i(i, orientation);
         }
     }
+
+    /* Scenes */
+
+    pub fn save_scene(&self, out: &mut scene::Writer) {
+        for entity in self.registry.iter() {
+            let i = entity.get_index() as usize;
+
+            out.push(
+                scene::Line::new("rigidbody")
+                    .handle(entity)
+                    .f32(self.masses[i])
+                    .f32(self.drags[i])
+                    .vec3(self.forces[i])
+                    .vec3(self.lin_velocities[i])
+                    .vec3(self.torques[i])
+                    .vec3(self.ang_velocities[i])
+            );
+        }
+    }
+
+    // Returns false for records belonging to other managers
+    pub fn load_scene(
+        &mut self,
+        kind: &str,
+        fields: &mut scene::Fields,
+    ) -> Result<bool, String> {
+        if kind != "rigidbody" {
+            return Ok(false);
+        }
+
+        let entity = fields.handle()?;
+
+        if self.has(entity) {
+            return Err(format!("duplicate rigidbody {}", entity));
+        }
+
+        let mass: f32 = fields.parse()?;
+        let drag: f32 = fields.parse()?;
+
+        // A mass of zero marks an uninitialized rigidbody
+        let valid = (mass == 0. || (mass > 0. && mass.is_finite()))
+            && drag >= 0. && drag.is_finite();
+
+        if !valid {
+            return Err(format!("invalid mass {} or drag {}", mass, drag));
+        }
+
+        self.register(entity);
+        let i = entity.get_index() as usize;
+
+        self.masses[i] = mass;
+        self.drags[i] = drag;
+        self.forces[i] = fields.vec3()?;
+        self.lin_velocities[i] = fields.vec3()?;
+        self.torques[i] = fields.vec3()?;
+        self.ang_velocities[i] = fields.vec3()?;
+
+        Ok(true)
+    }
+}
+
+fn validate(mass: f32, drag: f32) {
+    assert!(
+        mass > 0. && mass.is_finite(),
+        "Rigidbody mass must be positive and finite (got {})",
+        mass,
+    );
+
+    assert!(
+        drag >= 0. && drag.is_finite(),
+        "Rigidbody drag must be non-negative and finite (got {})",
+        drag,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+
+    #[test]
+    fn sparse_rigidbodies() {
+        let mut entities = entity::Manager::new(8);
+        let mut components = container();
+
+        let objects: Vec<entity::Handle> = (0..6).map(|_| {
+            let object = entities.add();
+            components.transforms.register(object);
+            object
+        }).collect();
+
+        // Only the last entity has a rigidbody
+        let body = objects[5];
+        components.rigidbodies.register(body);
+
+        // Not simulated until initialized
+        components.rigidbodies.simulate(&mut components.transforms, 0.01);
+        assert!(components.transforms.get_position(body) == alg::Vec3::zero());
+
+        components.rigidbodies.init(body, 2., 0.);
+        components.rigidbodies.set_force(body, alg::Vec3::up() * 100.);
+        components.rigidbodies.simulate(&mut components.transforms, 0.01);
+
+        assert!(components.transforms.get_position(body).y > 0.);
+
+        for object in &objects[..5] {
+            assert!(
+                components.transforms.get_position(*object)
+                    == alg::Vec3::zero()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_mass() {
+        let mut entities = entity::Manager::new(1);
+        let mut rigidbodies = rigidbody::Manager::new(1);
+
+        let object = entities.add();
+        rigidbodies.register(object);
+        rigidbodies.init(object, 0., 0.);
+    }
 }
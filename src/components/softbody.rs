@@ -1,4 +1,5 @@
 use alg;
+use scene;
 use entity;
 use render;
 use graphics;
@@ -7,7 +8,7 @@ use debug;
 
 use std;
 
-use ::FIXED_DT; // Import from lib
+use error::Error;
 use components::transform;
 
 // Constraint solver iterations
@@ -70,7 +71,7 @@ impl Rod {
     }
 }
 
-type Falloff = fn(alg::Vec3, alg::Vec3) -> alg::Vec3;
+pub type Falloff = fn(alg::Vec3, alg::Vec3) -> alg::Vec3;
 
 struct Magnet {
     target: alg::Vec3,
@@ -93,6 +94,20 @@ struct Range {
     max: f32,
 }
 
+// Index of an existing particle, for loading scenes
+fn particle(
+    instance: &Instance,
+    fields: &mut scene::Fields,
+) -> Result<usize, String> {
+    let index: usize = fields.parse()?;
+
+    if index >= instance.particles.len() {
+        return Err(format!("no particle {}", index));
+    }
+
+    Ok(index)
+}
+
 struct Joint {
     parent:  usize,
     child:   usize,
@@ -148,6 +163,7 @@ impl Instance {
         mass: f32,
         rigidity: f32,
         gravity: alg::Vec3,
+        delta: f32, // Fixed timestep
     ) -> Instance {
         debug_assert!(mass > 0.0);
         debug_assert!(rigidity > 0.0 && rigidity <= 0.5);
@@ -176,7 +192,7 @@ impl Instance {
             magnets: magnets,
 
             force: alg::Vec3::zero(),
-            accel_dt: gravity * FIXED_DT * FIXED_DT,
+            accel_dt: gravity * delta * delta,
             position: alg::Vec3::zero(),
 
             mass: mass,
@@ -191,10 +207,20 @@ impl Instance {
     }
 
     #[inline]
-    // Must be called when gravity or force changes
-    fn update_cache(&mut self, gravity: alg::Vec3) {
+    // Must be called when gravity, force, or the timestep changes
+    fn update_cache(&mut self, gravity: alg::Vec3, delta: f32) {
         self.accel_dt = ((self.force / self.mass) + gravity)
-            * FIXED_DT * FIXED_DT;
+            * delta * delta;
+    }
+
+    // Rescale implicit Verlet velocities to a new timestep
+    fn retime(&mut self, last_delta: f32, delta: f32) {
+        let ratio = delta / last_delta;
+
+        for particle in &mut self.particles {
+            let velocity = particle.position - particle.last;
+            particle.last = particle.position - velocity * ratio;
+        }
     }
 
     /* Limb helper methods */
@@ -386,10 +412,16 @@ pub struct Manager {
     joints: Vec<Joint>,
     planes: Vec<alg::Plane>,
     gravity: alg::Vec3,
+    delta: f32, // Fixed timestep
+    registry: components::Registry,
 }
 
 impl components::Component for Manager {
     fn register(&mut self, entity: entity::Handle) {
+        if !self.registry.register(entity) {
+            return;
+        }
+
         let i = entity.get_index() as usize;
 
         // Resize array to fit new entity
@@ -401,12 +433,30 @@ impl components::Component for Manager {
 
             break;
         }
+
+        // Uninitialized, and free of joints left by the previous owner
+        self.instances[i] = None;
+        self.joints.retain(|joint| joint.parent != i && joint.child != i);
+    }
+
+    // Also removes every joint attached to the entity
+    fn unregister(&mut self, entity: entity::Handle) {
+        if !self.registry.unregister(entity) {
+            return;
+        }
+
+        let i = entity.get_index() as usize;
+
+        self.instances[i] = None;
+        self.joints.retain(|joint| joint.parent != i && joint.child != i);
+    }
+
+    fn has(&self, entity: entity::Handle) -> bool {
+        self.registry.check(entity)
     }
 
-    // TODO: This currently only returns the length of the underlying data
-    // structure, not the count of the registered entities
     fn count(&self) -> usize {
-        self.instances.len()
+        self.registry.count()
     }
 }
 
@@ -421,6 +471,8 @@ impl Manager {
             joints: Vec::with_capacity(joint_hint),
             planes: Vec::with_capacity(plane_hint),
             gravity: alg::Vec3::new(0., -9.8, 0.),
+            delta: 1. / 100.,
+            registry: components::Registry::new(instance_hint),
         }
     }
 
@@ -433,8 +485,7 @@ impl Manager {
         bindings: &[(usize, usize)],
         magnets: &[(usize, Falloff)],
     ) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
+        let i = self.registry.index(entity);
 
         self.instances[i] = Some(
             Instance::new(
@@ -444,6 +495,7 @@ impl Manager {
                 mass,
                 rigidity * 0.5, // Scale rigidity properly
                 self.gravity,
+                self.delta,
             )
         );
     }
@@ -455,8 +507,7 @@ impl Manager {
         rigidity: f32, // Expects a value between 0-1
         scale: alg::Vec3,
     ) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
+        let i = self.registry.index(entity);
 
         let scale = scale * 0.5;
 
@@ -490,17 +541,17 @@ impl Manager {
                 mass,
                 rigidity * 0.5, // Scale rigidity properly
                 self.gravity,
+                self.delta,
             )
         );
     }
 
     pub fn set_force(&mut self, entity: entity::Handle, force: alg::Vec3) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
+        let i = self.registry.index(entity);
 
         if let Some(ref mut instance) = self.instances[i] {
             instance.force = force;
-            instance.update_cache(self.gravity);
+            instance.update_cache(self.gravity, self.delta);
         }
     }
 
@@ -510,8 +561,7 @@ impl Manager {
         index: usize,
         target: alg::Vec3,
     ) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
+        let i = self.registry.index(entity);
 
         if let Some(ref mut instance) = self.instances[i] {
             debug_assert!(index < instance.magnets.len());
@@ -525,8 +575,7 @@ impl Manager {
         entity: entity::Handle,
         index: usize,
     ) -> alg::Vec3 {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
+        let i = self.registry.index(entity);
 
         let mut position = alg::Vec3::zero();
 
@@ -542,20 +591,19 @@ impl Manager {
         &self,
         entity: entity::Handle,
     ) -> [render::PaddedVec3; render::MAX_SOFTBODY_VERT] {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.instances.len());
-
         // Default to no offsets (identity)
         let mut offsets = [
             render::PaddedVec3::default();
             render::MAX_SOFTBODY_VERT
         ];
 
-        // Space has not been allocated for this component (does not exist)
-        if i >= self.instances.len() {
+        // Component does not exist for this entity
+        if !self.registry.check(entity) {
             return offsets;
         }
 
+        let i = entity.get_index() as usize;
+
         // If the entity has a softbody component, fill the offsets array
         if let Some(ref instance) = self.instances[i] {
             for i in 0..instance.particles.len() {
@@ -575,13 +623,10 @@ impl Manager {
         z_limit: (f32, f32), // Degrees
     ) {
         let (i, j) = (
-            child.get_index() as usize,
-            parent.get_index() as usize,
+            self.registry.index(child),
+            self.registry.index(parent),
         );
 
-        debug_assert!(i < self.instances.len());
-        debug_assert!(j < self.instances.len());
-
         if self.instances[i].is_none() { return; }
         if self.instances[j].is_none() { return; }
 
@@ -614,7 +659,22 @@ impl Manager {
 
         for i in 0..self.instances.len() {
             if let Some(ref mut instance) = self.instances[i] {
-                instance.update_cache(self.gravity);
+                instance.update_cache(self.gravity, self.delta);
+            }
+        }
+    }
+
+    // Heavier call, but will force-update all instances
+    pub fn set_timestep(&mut self, delta: f32) {
+        debug_assert!(delta > 0.);
+
+        let last_delta = self.delta;
+        self.delta = delta;
+
+        for i in 0..self.instances.len() {
+            if let Some(ref mut instance) = self.instances[i] {
+                instance.retime(last_delta, self.delta);
+                instance.update_cache(self.gravity, self.delta);
             }
         }
     }
@@ -695,7 +755,8 @@ impl Manager {
             }
         }
 
-        // Solve joint constraints
+        // Solve joint constraints (joints to uninitialized instances are
+        // skipped)
         for joint in &self.joints {
             debug_assert!(joint.parent != joint.child);
 
@@ -708,14 +769,20 @@ impl Manager {
                 let ptr = self.instances.as_mut_ptr()
                     .offset(joint.parent as isize);
 
-                (*ptr).as_mut().unwrap()
+                match (*ptr).as_mut() {
+                    Some(instance) => instance,
+                    None => continue,
+                }
             };
 
             let mut child = unsafe {
                 let ptr = self.instances.as_mut_ptr()
                     .offset(joint.child as isize);
 
-                (*ptr).as_mut().unwrap()
+                match (*ptr).as_mut() {
+                    Some(instance) => instance,
+                    None => continue,
+                }
             };
 
             /* Constrain positions */
@@ -882,6 +949,285 @@ impl Manager {
         }
     }
 
+    /* Scenes */
+
+    // Magnet falloffs are written by name, and must be in the table
+    pub fn save_scene(
+        &self,
+        out: &mut scene::Writer,
+        falloffs: &scene::Falloffs,
+    ) -> Result<(), Error> {
+        out.push(scene::Line::new("gravity").vec3(self.gravity));
+        out.push(scene::Line::new("timestep").f32(self.delta));
+
+        for plane in &self.planes {
+            out.push(
+                scene::Line::new("plane").vec3(plane.normal).f32(plane.offset)
+            );
+        }
+
+        for entity in self.registry.iter() {
+            let instance = match self.instances[entity.get_index() as usize] {
+                Some(ref instance) => instance,
+                None => {
+                    out.push(
+                        scene::Line::new("softbody").handle(entity).word("none")
+                    );
+
+                    continue;
+                },
+            };
+
+            out.push(
+                scene::Line::new("softbody")
+                    .handle(entity)
+                    .f32(instance.mass)
+                    .f32(instance.rigidity)
+                    .vec3(instance.force)
+                    .vec3(instance.accel_dt)
+                    .vec3(instance.position)
+            );
+
+            for (particle, model) in instance.particles.iter()
+                .zip(&instance.model)
+            {
+                out.push(
+                    scene::Line::new("particle")
+                        .handle(entity)
+                        .vec3(particle.position)
+                        .vec3(particle.last)
+                        .vec3(*model)
+                );
+            }
+
+            for rod in &instance.rods {
+                out.push(
+                    scene::Line::new("rod")
+                        .handle(entity)
+                        .usize(rod.left)
+                        .usize(rod.right)
+                        .f32(rod.length)
+                );
+            }
+
+            for magnet in &instance.magnets {
+                // Compared by address, since function pointers can't be
+                // compared directly
+                let name = falloffs.iter()
+                    .find(|&&(_, f)| f as usize == magnet.falloff as usize)
+                    .map(|&(name, _)| name)
+                    .filter(|name| scene::is_word(name))
+                    .ok_or_else(|| {
+                        Error::Scene(
+                            format!("magnet on {} has no falloff name", entity)
+                        )
+                    })?;
+
+                out.push(
+                    scene::Line::new("magnet")
+                        .handle(entity)
+                        .usize(magnet.serf)
+                        .word(name)
+                        .vec3(magnet.target)
+                );
+            }
+        }
+
+        // Joints refer to instances by index
+        let handle = |index| {
+            self.registry.iter()
+                .find(|entity| entity.get_index() as usize == index)
+                .expect("Joint without an instance")
+        };
+
+        for joint in &self.joints {
+            out.push(
+                scene::Line::new("joint")
+                    .handle(handle(joint.parent))
+                    .handle(handle(joint.child))
+                    .f32(joint.x_limit.min)
+                    .f32(joint.x_limit.max)
+                    .f32(joint.y_limit.min)
+                    .f32(joint.y_limit.max)
+                    .f32(joint.z_limit.min)
+                    .f32(joint.z_limit.max)
+            );
+        }
+
+        Ok(())
+    }
+
+    // Returns false for records belonging to other managers
+    pub fn load_scene(
+        &mut self,
+        kind: &str,
+        fields: &mut scene::Fields,
+        falloffs: &scene::Falloffs,
+    ) -> Result<bool, String> {
+        use components::Component;
+
+        match kind {
+            "gravity" => self.gravity = fields.vec3()?,
+
+            "timestep" => {
+                let delta: f32 = fields.parse()?;
+
+                // The engine's fixed timestep can't follow the scene, so
+                // a different one wouldn't simulate as saved
+                if delta.to_bits() != self.delta.to_bits() {
+                    return Err(
+                        format!(
+                            "timestep {} doesn't match the engine's {}",
+                            delta,
+                            self.delta,
+                        )
+                    );
+                }
+            },
+
+            "plane" => {
+                let normal = fields.vec3()?;
+                let offset = fields.parse()?;
+
+                self.planes.push(alg::Plane::new_raw(normal, offset));
+            },
+
+            "softbody" => {
+                let entity = fields.handle()?;
+
+                if self.has(entity) {
+                    return Err(format!("duplicate softbody {}", entity));
+                }
+
+                let word = fields.word()?;
+
+                // Registered, but not initialized
+                if word == "none" {
+                    self.register(entity);
+                    return Ok(true);
+                }
+
+                let mass: f32 = word.parse()
+                    .map_err(|_| format!("invalid value \"{}\"", word))?;
+
+                let rigidity: f32 = fields.parse()?;
+
+                if !(mass > 0. && mass.is_finite()) {
+                    return Err(format!("invalid mass {}", mass));
+                }
+
+                if !(rigidity > 0. && rigidity <= 0.5) {
+                    return Err(format!("invalid rigidity {}", rigidity));
+                }
+
+                let instance = Instance {
+                    particles: Vec::new(),
+                    rods: Vec::new(),
+                    magnets: Vec::new(),
+                    force: fields.vec3()?,
+                    accel_dt: fields.vec3()?,
+                    position: fields.vec3()?,
+                    mass,
+                    model: Vec::new(),
+                    rigidity,
+                };
+
+                self.register(entity);
+                self.instances[entity.get_index() as usize] = Some(instance);
+            },
+
+            "particle" => {
+                let instance = self.loaded(fields.handle()?)?;
+
+                if instance.particles.len() == render::MAX_SOFTBODY_VERT {
+                    return Err("too many particles".into());
+                }
+
+                let position = fields.vec3()?;
+                let last = fields.vec3()?;
+                let model = fields.vec3()?;
+
+                instance.particles.push(Particle { position, last });
+                instance.model.push(model);
+            },
+
+            "rod" => {
+                let instance = self.loaded(fields.handle()?)?;
+                let left = particle(instance, fields)?;
+                let right = particle(instance, fields)?;
+                let length = fields.parse()?;
+
+                instance.rods.push(Rod { left, right, length });
+            },
+
+            "magnet" => {
+                let instance = self.loaded(fields.handle()?)?;
+                let serf = particle(instance, fields)?;
+                let name = fields.word()?;
+
+                let falloff = falloffs.iter()
+                    .find(|&&(candidate, _)| candidate == name)
+                    .map(|&(_, falloff)| falloff)
+                    .ok_or_else(|| format!("unknown falloff \"{}\"", name))?;
+
+                let target = fields.vec3()?;
+
+                instance.magnets.push(Magnet { target, serf, falloff });
+            },
+
+            "joint" => {
+                let parent = fields.handle()?;
+                let child = fields.handle()?;
+
+                // Joints constrain the first eight particles (see init_limb)
+                for entity in &[parent, child] {
+                    if self.loaded(*entity)?.particles.len() < 8 {
+                        return Err(format!("{} is not a limb", entity));
+                    }
+                }
+
+                if parent == child {
+                    return Err(format!("{} is jointed to itself", parent));
+                }
+
+                let mut range = || -> Result<Range, String> {
+                    Ok(Range { min: fields.parse()?, max: fields.parse()? })
+                };
+
+                let x_limit = range()?;
+                let y_limit = range()?;
+                let z_limit = range()?;
+
+                self.joints.push(
+                    Joint::new(
+                        parent.get_index() as usize,
+                        child.get_index() as usize,
+                        x_limit,
+                        y_limit,
+                        z_limit,
+                    )
+                );
+            },
+
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    // Initialized instance of a registered entity
+    fn loaded(
+        &mut self,
+        entity: entity::Handle,
+    ) -> Result<&mut Instance, String> {
+        if !self.registry.check(entity) {
+            return Err(format!("no softbody {}", entity));
+        }
+
+        self.instances[entity.get_index() as usize].as_mut()
+            .ok_or_else(|| format!("softbody {} has no instance", entity))
+    }
+
     #[allow(unused_variables)]
     pub fn draw_debug(
         &self,
@@ -889,7 +1235,7 @@ impl Manager {
         debug: &mut debug::Handler,
     ) {
         #[cfg(debug_assertions)] {
-            let i = entity.get_index() as usize;
+            let i = self.registry.index(entity);
             self.draw_instance_debug(i, debug);
         }
     }
@@ -930,11 +1276,10 @@ impl Manager {
             // Draw joint endpoints
             for joint in &self.joints {
                 if joint.child == index {
-                    let child = self.instances[joint.child]
-                        .as_ref().unwrap();
-
-                    let parent = self.instances[joint.parent]
-                        .as_ref().unwrap();
+                    let child = match self.instances[joint.child] {
+                        Some(ref instance) => instance,
+                        None => continue,
+                    };
 
                     debug.add_local_axes(
                         child.start(),
@@ -948,3 +1293,44 @@ impl Manager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+
+    #[test]
+    fn reuse_jointed_index() {
+        let mut entities = entity::Manager::new(2);
+        let mut components = container();
+
+        let objects: Vec<entity::Handle> = (0..2).map(|_| {
+            let object = entities.add();
+            components.transforms.register(object);
+            components.softbodies.register(object);
+            components.softbodies.init_limb(object, 1., 1., alg::Vec3::one());
+
+            object
+        }).collect();
+
+        components.softbodies.add_joint(
+            objects[0],
+            objects[1],
+            (-30., 30.),
+            (-30., 30.),
+            (-30., 30.),
+        );
+
+        // Removed without unregistering its components
+        entities.remove(objects[0]);
+
+        // The new entity doesn't inherit the joint
+        let object = entities.add();
+        assert!(object.get_index() == objects[0].get_index());
+        components.transforms.register(object);
+        components.softbodies.register(object);
+        components.softbodies.simulate(&mut components.transforms);
+    }
+}
@@ -1,12 +1,32 @@
 use alg;
+use scene;
 use entity;
 use components;
 
-// Data layout assumes that almost all entities will have this component
+// Data layout assumes that almost all entities will have this component.
+// Positions, orientations and scales are local (relative to the parent);
+// for entities without a parent, local and world space are the same.
 pub struct Manager {
     positions: Vec<alg::Vec3>,
     orientations: Vec<alg::Quat>,
     scales: Vec<alg::Vec3>,
+
+    // State at the previous fixed step, for interpolation
+    last_positions: Vec<alg::Vec3>,
+    last_orientations: Vec<alg::Quat>,
+
+    parents: Vec<Option<entity::Handle>>,
+
+    // Interpolated world matrices, written by propagate()
+    worlds: Vec<alg::Mat>,
+    dirty: Vec<bool>, // Changed since the last propagate()
+    moved: Vec<bool>, // World matrix rewritten by the last propagate()
+
+    // Registered indices, parents before children
+    order: Vec<usize>,
+    reorder: bool, // Hierarchy or membership changed since last sort
+
+    registry: components::Registry,
 }
 
 impl components::Component for Manager {
@@ -14,6 +34,10 @@ impl components::Component for Manager {
         debug_assert!(self.positions.len() == self.orientations.len());
         debug_assert!(self.orientations.len() == self.scales.len());
 
+        if !self.registry.register(entity) {
+            return;
+        }
+
         let i = entity.get_index() as usize;
 
         // Resize array to fit new entity
@@ -22,18 +46,47 @@ impl components::Component for Manager {
                 self.positions.push(alg::Vec3::zero());
                 self.orientations.push(alg::Quat::id());
                 self.scales.push(alg::Vec3::one());
+                self.last_positions.push(alg::Vec3::zero());
+                self.last_orientations.push(alg::Quat::id());
+                self.parents.push(None);
+                self.worlds.push(alg::Mat::id());
+                self.dirty.push(true);
+                self.moved.push(false);
 
                 continue;
             }
 
             break;
         }
+
+        // Identity transform, without a parent
+        self.positions[i] = alg::Vec3::zero();
+        self.orientations[i] = alg::Quat::id();
+        self.scales[i] = alg::Vec3::one();
+        self.last_positions[i] = alg::Vec3::zero();
+        self.last_orientations[i] = alg::Quat::id();
+        self.parents[i] = None;
+        self.worlds[i] = alg::Mat::id();
+        self.dirty[i] = true;
+        self.moved[i] = false;
+
+        self.reorder = true;
+    }
+
+    // Data is left in place until the index is registered again.
+    // Children of the entity are left without a parent.
+    fn unregister(&mut self, entity: entity::Handle) {
+        if self.registry.unregister(entity) {
+            self.reorder = true;
+        }
+    }
+
+    fn has(&self, entity: entity::Handle) -> bool {
+        self.registry.check(entity)
     }
 
-    // TODO: This currently only returns the length of the underlying data
-    // structure, not the count of the registered entities
     fn count(&self) -> usize {
-        self.positions.len()
+        self.registry.count()
     }
 }
 
@@ -43,9 +96,61 @@ impl Manager {
             positions: Vec::with_capacity(hint),
             orientations: Vec::with_capacity(hint),
             scales: Vec::with_capacity(hint),
+            last_positions: Vec::with_capacity(hint),
+            last_orientations: Vec::with_capacity(hint),
+            parents: Vec::with_capacity(hint),
+            worlds: Vec::with_capacity(hint),
+            dirty: Vec::with_capacity(hint),
+            moved: Vec::with_capacity(hint),
+            order: Vec::with_capacity(hint),
+            reorder: false,
+            registry: components::Registry::new(hint),
+        }
+    }
+
+    // Attach the child to the parent. The child's local transform is
+    // kept as-is, and is now relative to the parent.
+    pub fn set_parent(
+        &mut self,
+        child: entity::Handle,
+        parent: entity::Handle,
+    ) {
+        let i = self.registry.index(child);
+        self.registry.index(parent);
+
+        // Walk up from the parent to make sure this won't form a cycle
+        let mut ancestor = Some(parent);
+
+        while let Some(entity) = ancestor {
+            assert!(
+                entity != child,
+                "Parenting {} to {} would form a cycle",
+                child,
+                parent,
+            );
+
+            ancestor = self.get_parent(entity);
         }
+
+        self.parents[i] = Some(parent);
+        self.reorder = true;
+    }
+
+    // Detach the child, keeping its local transform
+    pub fn clear_parent(&mut self, child: entity::Handle) {
+        let i = self.registry.index(child);
+
+        self.parents[i] = None;
+        self.reorder = true;
+    }
+
+    // None if the parent was never set, or has since been unregistered
+    pub fn get_parent(&self, child: entity::Handle) -> Option<entity::Handle> {
+        let i = self.registry.index(child);
+        self.parents[i].filter(|parent| self.registry.check(*parent))
     }
 
+    // Teleports the entity (skips interpolation)
     pub fn set(
         &mut self,
         entity: entity::Handle,
@@ -53,13 +158,16 @@ impl Manager {
         orientation: alg::Quat,
         scale: alg::Vec3,
     ) {
-        let i = entity.get_index() as usize;
-
-        debug_assert!(i < self.positions.len());
+        let i = self.registry.index(entity);
 
         self.positions[i] = position;
         self.orientations[i] = orientation;
         self.scales[i] = scale;
+
+        self.last_positions[i] = position;
+        self.last_orientations[i] = orientation;
+
+        self.dirty[i] = true;
     }
 
     pub fn get(&self, entity: entity::Handle) -> (
@@ -67,8 +175,7 @@ impl Manager {
         alg::Quat,
         alg::Vec3,
     ) {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.positions.len());
+        let i = self.registry.index(entity);
 
         (
             self.positions[i],
@@ -77,31 +184,382 @@ impl Manager {
         )
     }
 
+    // Blend between the previous and current fixed step
+    pub fn get_interpolated(&self, entity: entity::Handle, alpha: f32) -> (
+        alg::Vec3,
+        alg::Quat,
+        alg::Vec3,
+    ) {
+        let i = self.registry.index(entity);
+
+        (
+            self.last_positions[i].lerp(self.positions[i], alpha),
+            self.last_orientations[i].nlerp(self.orientations[i], alpha),
+            self.scales[i],
+        )
+    }
+
+    // Store the current state for interpolation
+    // (called by the engine before every fixed step)
+    pub fn save_fixed(&mut self) {
+        // Entities coming to rest need to be drawn once more,
+        // at their final state
+        for i in 0..self.positions.len() {
+            if self.interpolating(i) {
+                self.dirty[i] = true;
+            }
+        }
+
+        self.last_positions.clone_from(&self.positions);
+        self.last_orientations.clone_from(&self.orientations);
+    }
+
     pub fn get_position(&self, entity: entity::Handle) -> alg::Vec3 {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.positions.len());
+        let i = self.registry.index(entity);
 
         self.positions[i]
     }
 
     pub fn get_orientation(&self, entity: entity::Handle) -> alg::Quat {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.positions.len());
+        let i = self.registry.index(entity);
 
         self.orientations[i]
     }
 
     pub fn get_scale(&self, entity: entity::Handle) -> alg::Vec3 {
-        let i = entity.get_index() as usize;
-        debug_assert!(i < self.positions.len());
+        let i = self.registry.index(entity);
 
         self.scales[i]
     }
 
+    pub fn get_local_matrix(&self, entity: entity::Handle) -> alg::Mat {
+        let i = self.registry.index(entity);
+
+        self.local_matrix_i(i)
+    }
+
+    // Current (uninterpolated) world matrix, walking up the hierarchy
+    pub fn get_world_matrix(&self, entity: entity::Handle) -> alg::Mat {
+        let i = self.registry.index(entity);
+
+        self.world_matrix_i(i)
+    }
+
+    pub fn get_world_position(&self, entity: entity::Handle) -> alg::Vec3 {
+        self.get_world_matrix(entity) * alg::Vec3::zero()
+    }
+
+    // Ignores any shear from non-uniformly scaled ancestors
+    pub fn get_world_orientation(&self, entity: entity::Handle) -> alg::Quat {
+        let i = self.registry.index(entity);
+
+        self.world_orientation_i(i)
+    }
+
+    /* Convenience */
+
+    // Move by an offset in world space
+    pub fn translate(&mut self, entity: entity::Handle, offset: alg::Vec3) {
+        let i = self.registry.index(entity);
+        self.translate_i(i, offset);
+    }
+
+    // Move by an offset along the entity's own axes
+    pub fn translate_local(
+        &mut self,
+        entity: entity::Handle,
+        offset: alg::Vec3,
+    ) {
+        let i = self.registry.index(entity);
+        self.translate_local_i(i, offset);
+    }
+
+    // Rotate in place, about world axes
+    pub fn rotate(&mut self, entity: entity::Handle, rotation: alg::Quat) {
+        let i = self.registry.index(entity);
+        self.rotate_i(i, rotation);
+    }
+
+    // Rotate in place, about the entity's own axes
+    pub fn rotate_local(
+        &mut self,
+        entity: entity::Handle,
+        rotation: alg::Quat,
+    ) {
+        let i = self.registry.index(entity);
+        self.rotate_local_i(i, rotation);
+    }
+
+    // Turn the entity's forward axis towards a point in world space.
+    // The orientation is left unchanged if the point is the entity's
+    // position, or lies straight along the up axis.
+    pub fn look_at(
+        &mut self,
+        entity: entity::Handle,
+        target: alg::Vec3,
+        up: alg::Vec3,
+    ) {
+        let i = self.registry.index(entity);
+        self.look_at_i(i, target, up);
+    }
+
+    // Basis vectors in world space
+
+    pub fn forward(&self, entity: entity::Handle) -> alg::Vec3 {
+        let i = self.registry.index(entity);
+        self.forward_i(i)
+    }
+
+    pub fn up(&self, entity: entity::Handle) -> alg::Vec3 {
+        let i = self.registry.index(entity);
+        self.up_i(i)
+    }
+
+    pub fn right(&self, entity: entity::Handle) -> alg::Vec3 {
+        let i = self.registry.index(entity);
+        self.right_i(i)
+    }
+
+    // Interpolated world matrix, as of the last call to propagate()
+    pub fn get_drawn_matrix(&self, entity: entity::Handle) -> alg::Mat {
+        let i = self.registry.index(entity);
+
+        self.worlds[i]
+    }
+
+    // Whether the last call to propagate() changed the drawn matrix
+    pub fn moved(&self, entity: entity::Handle) -> bool {
+        let i = self.registry.index(entity);
+
+        self.moved[i]
+    }
+
+    // Compute the interpolated world matrix of every entity,
+    // parents before children (called by the engine before drawing).
+    // Only entities that changed, are between two different fixed states,
+    // or whose parent moved are recomputed.
+    pub fn propagate(&mut self, alpha: f32) {
+        if self.reorder {
+            self.sort();
+        }
+
+        for &i in &self.order {
+            let parent_moved = match self.parent_i(i) {
+                Some(parent) => self.moved[parent],
+                None => false,
+            };
+
+            let moved = self.dirty[i] || self.interpolating(i) || parent_moved;
+
+            self.dirty[i] = false;
+            self.moved[i] = moved;
+
+            if !moved {
+                continue;
+            }
+
+            let local = model(
+                self.last_positions[i].lerp(self.positions[i], alpha),
+                self.last_orientations[i].nlerp(self.orientations[i], alpha),
+                self.scales[i],
+            );
+
+            self.worlds[i] = match self.parent_i(i) {
+                Some(parent) => self.worlds[parent] * local,
+                None => local,
+            };
+        }
+    }
+
+    fn local_matrix_i(&self, index: usize) -> alg::Mat {
+        model(
+            self.positions[index],
+            self.orientations[index],
+            self.scales[index],
+        )
+    }
+
+    fn world_matrix_i(&self, index: usize) -> alg::Mat {
+        let mut world = self.local_matrix_i(index);
+        let mut i = index;
+
+        while let Some(parent) = self.parent_i(i) {
+            world = self.local_matrix_i(parent) * world;
+            i = parent;
+        }
+
+        world
+    }
+
+    fn world_orientation_i(&self, index: usize) -> alg::Quat {
+        self.parent_orientation_i(index) * self.orientations[index]
+    }
+
+    // World orientation of the parent space (identity for root entities)
+    fn parent_orientation_i(&self, index: usize) -> alg::Quat {
+        let mut orientation = alg::Quat::id();
+        let mut i = index;
+
+        while let Some(parent) = self.parent_i(i) {
+            orientation = self.orientations[parent] * orientation;
+            i = parent;
+        }
+
+        orientation
+    }
+
+    // World scale of the parent space (ignores shear)
+    fn parent_scale_i(&self, index: usize) -> alg::Vec3 {
+        let mut scale = alg::Vec3::one();
+        let mut i = index;
+
+        while let Some(parent) = self.parent_i(i) {
+            scale = alg::Vec3::new(
+                scale.x * self.scales[parent].x,
+                scale.y * self.scales[parent].y,
+                scale.z * self.scales[parent].z,
+            );
+
+            i = parent;
+        }
+
+        scale
+    }
+
+    // Whether the interpolated state depends on alpha
+    fn interpolating(&self, index: usize) -> bool {
+        self.last_positions[index] != self.positions[index]
+            || self.last_orientations[index] != self.orientations[index]
+    }
+
+    // Index of the registered parent at the index, if any
+    fn parent_i(&self, index: usize) -> Option<usize> {
+        match self.parents[index] {
+            Some(parent) if self.registry.check(parent) => {
+                Some(parent.get_index() as usize)
+            },
+
+            _ => None,
+        }
+    }
+
+    // Rebuild the topological order with a depth-first walk from the roots
+    fn sort(&mut self) {
+        let mut children = vec![Vec::new(); self.parents.len()];
+        let mut stack = Vec::new();
+
+        for entity in self.registry.iter() {
+            let i = entity.get_index() as usize;
+
+            match self.parent_i(i) {
+                Some(parent) => children[parent].push(i),
+                None => stack.push(i),
+            }
+        }
+
+        self.order.clear();
+
+        while let Some(i) = stack.pop() {
+            self.order.push(i);
+            stack.extend_from_slice(&children[i]);
+        }
+
+        debug_assert!(self.order.len() == self.registry.count());
+        self.reorder = false;
+
+        // Subtrees may have moved; recompute everything once
+        for dirty in &mut self.dirty {
+            *dirty = true;
+        }
+    }
+
+    /* Scenes */
+
+    // Parents are written after every transform, and stale ones are dropped
+    pub fn save_scene(&self, out: &mut scene::Writer) {
+        for entity in self.registry.iter() {
+            let i = entity.get_index() as usize;
+
+            out.push(
+                scene::Line::new("transform")
+                    .handle(entity)
+                    .vec3(self.positions[i])
+                    .quat(self.orientations[i])
+                    .vec3(self.scales[i])
+                    .vec3(self.last_positions[i])
+                    .quat(self.last_orientations[i])
+            );
+        }
+
+        for child in self.registry.iter() {
+            if let Some(parent) = self.get_parent(child) {
+                out.push(
+                    scene::Line::new("parent").handle(child).handle(parent)
+                );
+            }
+        }
+    }
+
+    // Returns false for records belonging to other managers
+    pub fn load_scene(
+        &mut self,
+        kind: &str,
+        fields: &mut scene::Fields,
+    ) -> Result<bool, String> {
+        use components::Component;
+
+        match kind {
+            "transform" => {
+                let entity = fields.handle()?;
+
+                if self.has(entity) {
+                    return Err(format!("duplicate transform {}", entity));
+                }
+
+                self.register(entity);
+                let i = entity.get_index() as usize;
+
+                self.positions[i] = fields.vec3()?;
+                self.orientations[i] = fields.quat()?;
+                self.scales[i] = fields.vec3()?;
+                self.last_positions[i] = fields.vec3()?;
+                self.last_orientations[i] = fields.quat()?;
+            },
+
+            "parent" => {
+                let child = fields.handle()?;
+                let parent = fields.handle()?;
+
+                for entity in &[child, parent] {
+                    if !self.has(*entity) {
+                        return Err(format!("no transform {}", entity));
+                    }
+                }
+
+                let mut ancestor = Some(parent);
+
+                while let Some(entity) = ancestor {
+                    if entity == child {
+                        return Err(format!("cycle through {}", child));
+                    }
+
+                    ancestor = self.get_parent(entity);
+                }
+
+                self.set_parent(child, parent);
+            },
+
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
     /* Tight coupling--beware */
 
     pub fn set_position_i(&mut self, index: usize, value: alg::Vec3) {
         self.positions[index] = value;
+        self.dirty[index] = true;
     }
 
     pub fn get_position_i(&self, index: usize) -> alg::Vec3 {
@@ -110,9 +568,319 @@ impl Manager {
 
     pub fn set_orientation_i(&mut self, index: usize, value: alg::Quat) {
         self.orientations[index] = value;
+        self.dirty[index] = true;
     }
 
     pub fn get_orientation_i(&self, index: usize) -> alg::Quat {
         self.orientations[index]
     }
+
+    pub fn translate_i(&mut self, index: usize, offset: alg::Vec3) {
+        // Bring the offset into the parent's space
+        let rotated = self.parent_orientation_i(index).conjugate() * offset;
+        let scale = self.parent_scale_i(index);
+
+        let offset = alg::Vec3::new(
+            rotated.x / scale.x,
+            rotated.y / scale.y,
+            rotated.z / scale.z,
+        );
+
+        let position = self.positions[index] + offset;
+        self.set_position_i(index, position);
+    }
+
+    pub fn translate_local_i(&mut self, index: usize, offset: alg::Vec3) {
+        let position = self.positions[index]
+            + self.orientations[index] * offset;
+
+        self.set_position_i(index, position);
+    }
+
+    pub fn rotate_i(&mut self, index: usize, rotation: alg::Quat) {
+        // Conjugate the rotation into the parent's space
+        let parent = self.parent_orientation_i(index);
+        let rotation = parent.conjugate() * rotation * parent;
+
+        let orientation = (rotation * self.orientations[index]).norm();
+        self.set_orientation_i(index, orientation);
+    }
+
+    pub fn rotate_local_i(&mut self, index: usize, rotation: alg::Quat) {
+        let orientation = (self.orientations[index] * rotation).norm();
+        self.set_orientation_i(index, orientation);
+    }
+
+    pub fn look_at_i(
+        &mut self,
+        index: usize,
+        target: alg::Vec3,
+        up: alg::Vec3,
+    ) {
+        let position = self.world_matrix_i(index) * alg::Vec3::zero();
+
+        let offset = target - position;
+
+        if offset.mag_squared() < f32::EPSILON {
+            return;
+        }
+
+        // Same basis as alg::Mat::look_at_view()
+        let fwd = offset.norm();
+        let right = up.cross(fwd);
+
+        if right.mag_squared() < f32::EPSILON {
+            return;
+        }
+
+        let right = right.norm();
+        let up = fwd.cross(right);
+
+        let world = alg::Quat::from_mat(alg::Mat::axes(right, up, fwd));
+        let local = self.parent_orientation_i(index).conjugate() * world;
+
+        self.set_orientation_i(index, local.norm());
+    }
+
+    pub fn forward_i(&self, index: usize) -> alg::Vec3 {
+        self.world_orientation_i(index) * alg::Vec3::fwd()
+    }
+
+    pub fn up_i(&self, index: usize) -> alg::Vec3 {
+        self.world_orientation_i(index) * alg::Vec3::up()
+    }
+
+    pub fn right_i(&self, index: usize) -> alg::Vec3 {
+        self.world_orientation_i(index) * alg::Vec3::right()
+    }
+}
+
+fn model(position: alg::Vec3, orientation: alg::Quat, scale: alg::Vec3)
+    -> alg::Mat
+{
+    alg::Mat::translation_vec(position)
+        * orientation.to_mat()
+        * alg::Mat::scale_vec(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std;
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+
+    #[test]
+    fn reuse_index() {
+        let mut entities = entity::Manager::new(2);
+        let mut transforms = transform::Manager::new(2);
+
+        let a = entities.add();
+        transforms.register(a);
+        transforms.set(a, alg::Vec3::one(), alg::Quat::id(), alg::Vec3::one());
+        entities.remove(a);
+
+        // The new entity starts from a clean transform
+        let b = entities.add();
+        transforms.register(b);
+        assert!(b.get_index() == a.get_index());
+        assert!(transforms.get_position(b) == alg::Vec3::zero());
+
+        assert!(transforms.has(b));
+        assert!(!transforms.has(a));
+    }
+
+    #[test]
+    #[should_panic]
+    fn stale_handle() {
+        let mut entities = entity::Manager::new(2);
+        let mut transforms = transform::Manager::new(2);
+
+        let a = entities.add();
+        transforms.register(a);
+        entities.remove(a);
+
+        let b = entities.add();
+        transforms.register(b);
+
+        // Can't drive the new entity's transform
+        transforms.set(a, alg::Vec3::one(), alg::Quat::id(), alg::Vec3::one());
+    }
+
+    #[test]
+    fn hierarchy() {
+        let mut entities = entity::Manager::new(4);
+        let mut components = container();
+
+        // Children come first in index order
+        let weapon = entities.add();
+        let hand = entities.add();
+        let player = entities.add();
+
+        for &entity in &[weapon, hand, player] {
+            components.transforms.register(entity);
+            components.draws.register(entity, 0);
+        }
+
+        components.transforms.set(
+            player,
+            alg::Vec3::new(1., 0., 0.),
+            alg::Quat::id(),
+            alg::Vec3::one() * 2.,
+        );
+
+        components.transforms.set(
+            hand,
+            alg::Vec3::new(0., 1., 0.),
+            alg::Quat::id(),
+            alg::Vec3::one(),
+        );
+
+        components.transforms.set(
+            weapon,
+            alg::Vec3::new(0., 0., 1.),
+            alg::Quat::id(),
+            alg::Vec3::one(),
+        );
+
+        components.transforms.set_parent(hand, player);
+        components.transforms.set_parent(weapon, hand);
+        assert!(components.transforms.get_parent(weapon) == Some(hand));
+        assert!(components.transforms.get_parent(player).is_none());
+
+        // Local values are unchanged
+        assert!(
+            components.transforms.get_position(weapon)
+                == alg::Vec3::new(0., 0., 1.)
+        );
+
+        assert!(
+            components.transforms.get_world_position(weapon)
+                == alg::Vec3::new(1., 2., 2.)
+        );
+
+        // Scale is inherited too
+        let scale = alg::Mat::scale(2., 2., 2.);
+
+        assert!(
+            drawn(&mut components) == vec![
+                alg::Mat::translation(1., 2., 2.) * scale,
+                alg::Mat::translation(1., 2., 0.) * scale,
+                alg::Mat::translation(1., 0., 0.) * scale,
+            ]
+        );
+
+        // Orphaned children fall back on their local transform
+        components.transforms.unregister(player);
+        assert!(components.transforms.get_parent(hand).is_none());
+        assert!(
+            components.transforms.get_world_position(weapon)
+                == alg::Vec3::new(0., 1., 1.)
+        );
+
+        components.transforms.clear_parent(weapon);
+        assert!(
+            components.transforms.get_world_matrix(weapon)
+                == alg::Mat::translation(0., 0., 1.)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn hierarchy_cycle() {
+        let mut entities = entity::Manager::new(3);
+        let mut transforms = transform::Manager::new(3);
+
+        let a = entities.add();
+        let b = entities.add();
+        let c = entities.add();
+
+        for &entity in &[a, b, c] {
+            transforms.register(entity);
+        }
+
+        transforms.set_parent(b, a);
+        transforms.set_parent(c, b);
+        transforms.set_parent(a, c);
+    }
+
+    #[test]
+    fn convenience() {
+        let close = |a: alg::Vec3, b: alg::Vec3| (a - b).mag() < 0.0001;
+
+        let mut entities = entity::Manager::new(2);
+        let mut transforms = transform::Manager::new(2);
+
+        let parent = entities.add();
+        let child = entities.add();
+        transforms.register(parent);
+        transforms.register(child);
+        transforms.set_parent(child, parent);
+
+        // Turned to face +X, and scaled up
+        transforms.set(
+            parent,
+            alg::Vec3::zero(),
+            alg::Quat::axis_angle(alg::Vec3::up(), 0.5 * std::f32::consts::PI),
+            alg::Vec3::one() * 2.,
+        );
+
+        assert!(close(transforms.forward(parent), alg::Vec3::right()));
+        assert!(close(transforms.right(parent), -alg::Vec3::fwd()));
+        assert!(close(transforms.up(child), alg::Vec3::up()));
+
+        // Along the parent's forward axis, in the parent's units
+        transforms.translate_local(child, alg::Vec3::fwd());
+        assert!(
+            close(transforms.get_world_position(child), alg::Vec3::right() * 2.)
+        );
+
+        // World offsets are unaffected by the parent
+        transforms.translate(child, alg::Vec3::up());
+        assert!(
+            close(
+                transforms.get_world_position(child),
+                alg::Vec3::new(2., 1., 0.),
+            )
+        );
+
+        // Turn to face along +X, then back towards the origin
+        transforms.look_at(child, alg::Vec3::new(4., 1., 0.), alg::Vec3::up());
+        assert!(close(transforms.forward(child), alg::Vec3::right()));
+
+        transforms.look_at(child, alg::Vec3::new(0., 1., 0.), alg::Vec3::up());
+        assert!(close(transforms.forward(child), -alg::Vec3::right()));
+        assert!(close(transforms.up(child), alg::Vec3::up()));
+
+        // Degenerate targets leave the orientation alone
+        let position = transforms.get_world_position(child);
+        transforms.look_at(child, position, alg::Vec3::up());
+        transforms.look_at(child, position + alg::Vec3::up(), alg::Vec3::up());
+        assert!(close(transforms.forward(child), -alg::Vec3::right()));
+        assert!(close(transforms.up(child), alg::Vec3::up()));
+
+        // A world yaw and a local yaw agree about the up axis
+        let quarter = alg::Quat::axis_angle(
+            alg::Vec3::up(),
+            0.5 * std::f32::consts::PI,
+        );
+
+        transforms.rotate(child, quarter);
+        assert!(close(transforms.forward(child), alg::Vec3::fwd()));
+
+        transforms.rotate_local(child, quarter);
+        assert!(close(transforms.forward(child), alg::Vec3::right()));
+
+        // Local pitch tilts the forward axis down
+        transforms.rotate_local(
+            child,
+            alg::Quat::axis_angle(
+                alg::Vec3::right(),
+                0.5 * std::f32::consts::PI,
+            ),
+        );
+
+        assert!(close(transforms.forward(child), -alg::Vec3::up()));
+    }
 }
@@ -1,12 +1,82 @@
+use std;
 use ini;
 
+use error::Error;
+
 lazy_static! {
-    pub static ref ENGINE_CONFIG: ini::Ini = {
-        load_config("config.ini")
+    // Errors are stored as messages, so that the config can be shared
+    pub static ref ENGINE_CONFIG: Result<ini::Ini, String> = {
+        load_config("config.ini").map_err(|e| e.to_string())
     };
 }
 
-pub fn load_config(filename: &str) -> ini::Ini {
-    let conf = ini::Ini::load_from_file(filename).unwrap();
-    conf
+pub fn load_config(filename: &str) -> Result<ini::Ini, Error> {
+    ini::Ini::load_from_file(filename).map_err(|e| {
+        Error::Config(format!("could not load \"{}\": {}", filename, e))
+    })
+}
+
+// Get and parse a value from the engine config, if it exists
+pub fn get<T>(section: &str, key: &str) -> Result<Option<T>, Error>
+where
+    T: std::str::FromStr
+{
+    let config = match *ENGINE_CONFIG {
+        Ok(ref config) => config,
+        Err(ref e) => return Err(Error::Config(e.clone())),
+    };
+
+    let value = config.section(Some(section))
+        .and_then(|properties| properties.get(key));
+
+    match value {
+        Some(value) => match value.parse() {
+            Ok(result) => Ok(Some(result)),
+            Err(_) => Err(
+                Error::Config(
+                    format!(
+                        "invalid value \"{}\" for \"{}\" in section [{}]",
+                        value,
+                        key,
+                        section,
+                    )
+                )
+            ),
+        },
+
+        None => Ok(None),
+    }
+}
+
+// Get and parse a value from the engine config, failing if it doesn't exist
+pub fn require<T>(section: &str, key: &str) -> Result<T, Error>
+where
+    T: std::str::FromStr
+{
+    match get(section, key)? {
+        Some(value) => Ok(value),
+        None => Err(
+            Error::Config(
+                format!("missing \"{}\" in section [{}]", key, section)
+            )
+        ),
+    }
+}
+
+// Get every key-value pair in a section of the engine config (may be empty)
+pub fn section(section: &str) -> Result<Vec<(String, String)>, Error> {
+    let config = match *ENGINE_CONFIG {
+        Ok(ref config) => config,
+        Err(ref e) => return Err(Error::Config(e.clone())),
+    };
+
+    Ok(
+        config.section(Some(section))
+            .map(|properties| {
+                properties.iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    )
 }
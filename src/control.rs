@@ -0,0 +1,97 @@
+// Engine requests made by the game from its callbacks.
+// Requests take effect on the next frame.
+pub struct Handler {
+    exit: bool,
+    paused: bool,
+    steps: u32, // Fixed steps requested while paused
+    time_scale: f32,
+}
+
+impl Handler {
+    pub fn new() -> Handler {
+        Handler {
+            exit: false,
+            paused: false,
+            steps: 0,
+            time_scale: 1.,
+        }
+    }
+
+    // Stop the engine loop
+    pub fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    pub fn exiting(&self) -> bool {
+        self.exit
+    }
+
+    // Stop running fixed updates (update is still called every frame)
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps = 0;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    // Pause, then advance by exactly one fixed step
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.steps += 1;
+    }
+
+    // Multiplies frame time before it is fed to the fixed update loop
+    pub fn set_time_scale(&mut self, scale: f32) {
+        assert!(scale >= 0.);
+        self.time_scale = scale;
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /* Engine hooks */
+
+    // Consume up to max pending steps
+    pub fn take_steps(&mut self, max: u32) -> u32 {
+        let steps = self.steps.min(max);
+        self.steps -= steps;
+        steps
+    }
+}
+
+impl Default for Handler {
+    fn default() -> Handler {
+        Handler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use control::*;
+
+    #[test]
+    fn steps() {
+        let mut control = Handler::new();
+        assert!(!control.paused());
+        assert!(control.take_steps(8) == 0);
+
+        control.step();
+        control.step();
+        control.step();
+        assert!(control.paused());
+        assert!(control.take_steps(2) == 2);
+        assert!(control.take_steps(2) == 1);
+
+        control.step();
+        control.resume();
+        assert!(!control.paused());
+        assert!(control.take_steps(8) == 0);
+    }
+}
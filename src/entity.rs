@@ -1,82 +1,704 @@
 use std;
+use scene;
+use components;
 
+use error::Error;
+
+// Index into component storage, plus the generation of the entity
+// that owned the index when the handle was created
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
 pub struct Handle {
-    _value: u32,
+    index: u32,
+    generation: u32,
 }
 
 impl Handle {
-    fn new(index: u32) -> Handle {
+    fn new(index: u32, generation: u32) -> Handle {
         Handle {
-            _value: index,
+            index,
+            generation,
         }
     }
 
     pub fn get_index(self) -> u32 {
-        self._value
+        self.index
+    }
+
+    pub fn get_generation(self) -> u32 {
+        self.generation
     }
 }
 
 impl std::fmt::Display for Handle {
     fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(out, "{}", self._value)
+        write!(out, "{}:{}", self.index, self.generation)
+    }
+}
+
+impl std::fmt::Debug for Handle {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(out, "Handle({})", self)
+    }
+}
+
+// Parses the "index:generation" form written by Display
+impl std::str::FromStr for Handle {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(text: &str) -> Result<Handle, Self::Err> {
+        let mut parts = text.splitn(2, ':');
+        let index = parts.next().unwrap_or("").parse()?;
+        let generation = parts.next().unwrap_or("").parse()?;
+
+        Ok(Handle::new(index, generation))
+    }
+}
+
+// Handle alongside the entity's name, if it has one (e.g. "left_arm (7:0)")
+pub struct Label<'a> {
+    handle: Handle,
+    name: Option<&'a str>,
+}
+
+impl<'a> std::fmt::Display for Label<'a> {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.name {
+            Some(name) => write!(out, "{} ({})", name, self.handle),
+            None => write!(out, "{}", self.handle),
+        }
     }
 }
 
+// Indices of removed entities are recycled, so that component storage
+// stays proportional to the number of live entities
 pub struct Manager {
-    data:  std::collections::HashSet<Handle>,
-    index: u32,
+    generations: Vec<u32>, // Current generation at each index
+    alive: Vec<bool>,
+    free: Vec<u32>, // Removed indices, ready for reuse
+    reserved: Vec<Handle>, // Reserved, but not yet spawned
     count: u32,
+
+    // Optional, unique names
+    names: Vec<Option<String>>,
+    named: std::collections::HashMap<String, Handle>,
+
+    // Per entity, in the order they were added
+    tags: Vec<Vec<String>>,
+    tagged: std::collections::HashMap<String, Vec<Handle>>,
+}
+
+impl std::fmt::Debug for Manager {
+    // Live entities, with their names and tags
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut list = out.debug_list();
+
+        for handle in self.iter() {
+            let tags = self.get_tags(handle);
+
+            if tags.is_empty() {
+                list.entry(&format_args!("{}", self.label(handle)));
+            } else {
+                list.entry(
+                    &format_args!(
+                        "{} [{}]",
+                        self.label(handle),
+                        tags.join(", "),
+                    )
+                );
+            }
+        }
+
+        list.finish()
+    }
 }
 
 impl Manager {
     pub fn new(hint: usize) -> Manager {
         Manager {
-            data:  std::collections::HashSet::with_capacity(hint),
-            index: 0,
+            generations: Vec::with_capacity(hint),
+            alive: Vec::with_capacity(hint),
+            free: Vec::new(),
+            reserved: Vec::new(),
             count: 0,
+            names: Vec::with_capacity(hint),
+            named: std::collections::HashMap::new(),
+            tags: Vec::with_capacity(hint),
+            tagged: std::collections::HashMap::new(),
         }
     }
 
     pub fn add(&mut self) -> Handle {
-        if self.count == u32::max_value() {
-            panic!("Out of space for new entities!");
-        }
+        let handle = self.reserve();
+        self.spawn(handle);
 
-        loop {
-            // If an entity already exists, skip
-            if self.check(Handle::new(self.index)) {
-                self.index = self.index.wrapping_add(1);
-                continue;
-            }
+        handle
+    }
 
-            break;
-        }
+    // Allocate a handle that isn't alive until it is spawned
+    // (used by command buffers to hand out handles early)
+    pub fn reserve(&mut self) -> Handle {
+        // Reuse the most recently freed index, if any
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                if self.generations.len() == u32::max_value() as usize {
+                    panic!("Out of space for new entities!");
+                }
 
-        // Add new entity
-        let handle = Handle::new(self.index);
-        self.data.insert(handle);
-        self.count += 1;
+                self.generations.push(0);
+                self.alive.push(false);
+                self.names.push(None);
+                self.tags.push(Vec::new());
+
+                (self.generations.len() - 1) as u32
+            },
+        };
 
-        // Offset for next time
-        self.index = self.index.wrapping_add(1);
+        let i = index as usize;
+        debug_assert!(!self.alive[i]);
+
+        let handle = Handle::new(index, self.generations[i]);
+        self.reserved.push(handle);
 
         handle
     }
 
+    // Bring a reserved handle to life
+    pub fn spawn(&mut self, handle: Handle) {
+        let i = handle.index as usize;
+
+        let j = self.reserved.iter().rposition(|&other| other == handle);
+        let j = j.unwrap_or_else(|| {
+            panic!("Entity handle {} was not reserved", handle)
+        });
+
+        self.reserved.swap_remove(j);
+        debug_assert!(!self.alive[i]);
+
+        // Add new entity
+        self.alive[i] = true;
+        self.count += 1;
+    }
+
+    // False for removed entities, even if their index has been reused
     pub fn check(&self, handle: Handle) -> bool {
-        self.data.contains(&handle)
+        let i = handle.index as usize;
+
+        i < self.alive.len()
+            && self.alive[i]
+            && self.generations[i] == handle.generation
     }
 
-    // Idempotent--but access a handle after remove() at your own risk!
+    // Free the reserved handles that were never spawned (e.g. their commands
+    // were dropped before being applied). The handles stay stale.
+    pub fn release_reserved(&mut self) {
+        for handle in std::mem::take(&mut self.reserved) {
+            let i = handle.index as usize;
+
+            self.generations[i] = self.generations[i].wrapping_add(1);
+            self.free.push(handle.index);
+        }
+    }
+
+    // Idempotent; stale handles are ignored.
+    // Leaves components registered--use destroy() to clean them up.
     pub fn remove(&mut self, handle: Handle) {
-        if self.data.remove(&handle) {
-            // Decrement counter
-            self.count -= 1;
+        if !self.check(handle) {
+            return;
         }
+
+        let i = handle.index as usize;
+
+        // Forget the name and tags
+        self.clear_name(handle);
+
+        for tag in std::mem::take(&mut self.tags[i]) {
+            self.forget_tag(handle, &tag);
+        }
+
+        // Invalidate existing handles
+        self.alive[i] = false;
+        self.generations[i] = self.generations[i].wrapping_add(1);
+        self.free.push(handle.index);
+
+        // Decrement counter
+        self.count -= 1;
+    }
+
+    // Remove the entity along with all of its components
+    // (including softbody joints and its render instance)
+    pub fn destroy(
+        &mut self,
+        handle: Handle,
+        components: &mut components::Container,
+    ) {
+        if !self.check(handle) {
+            return;
+        }
+
+        components.unregister(handle);
+        self.remove(handle);
+    }
+
+    // Live handles, in index order
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Handle> + 'a {
+        self.alive.iter()
+            .zip(&self.generations)
+            .enumerate()
+            .filter(|&(_, (&alive, _))| alive)
+            .map(|(i, (_, &generation))| Handle::new(i as u32, generation))
+    }
+
+    // Live handles with every component in the signature
+    pub fn query<'a>(
+        &'a self,
+        components: &'a components::Container,
+        signature: components::Signature,
+    ) -> impl Iterator<Item = Handle> + 'a {
+        components.query(self, signature)
     }
 
     pub fn count(&self) -> usize {
-        self.data.len()
+        self.count as usize
+    }
+
+    /* Names and tags */
+
+    // Replaces the entity's previous name.
+    // Panics if another entity already has the name.
+    pub fn set_name(&mut self, handle: Handle, name: &str) {
+        self.index(handle);
+
+        if let Some(&owner) = self.named.get(name) {
+            assert!(
+                owner == handle,
+                "Entity name \"{}\" is already taken by {}",
+                name,
+                owner,
+            );
+
+            return;
+        }
+
+        self.clear_name(handle);
+
+        self.names[handle.index as usize] = Some(name.to_string());
+        self.named.insert(name.to_string(), handle);
+    }
+
+    pub fn clear_name(&mut self, handle: Handle) {
+        if !self.check(handle) {
+            return;
+        }
+
+        if let Some(name) = self.names[handle.index as usize].take() {
+            self.named.remove(&name);
+        }
+    }
+
+    // None for stale handles
+    pub fn get_name(&self, handle: Handle) -> Option<&str> {
+        if !self.check(handle) {
+            return None;
+        }
+
+        self.names[handle.index as usize].as_deref()
+    }
+
+    // Live entity with the name, if any
+    pub fn find(&self, name: &str) -> Option<Handle> {
+        self.named.get(name).cloned()
+    }
+
+    // For debug output, e.g. println!("{}", entities.label(handle))
+    pub fn label<'a>(&'a self, handle: Handle) -> Label<'a> {
+        Label {
+            handle,
+            name: self.get_name(handle),
+        }
+    }
+
+    // Idempotent
+    pub fn tag(&mut self, handle: Handle, tag: &str) {
+        let i = self.index(handle);
+
+        if self.tags[i].iter().any(|existing| existing == tag) {
+            return;
+        }
+
+        self.tags[i].push(tag.to_string());
+        self.tagged.entry(tag.to_string())
+            .or_default()
+            .push(handle);
+    }
+
+    pub fn untag(&mut self, handle: Handle, tag: &str) {
+        if !self.check(handle) {
+            return;
+        }
+
+        let tags = &mut self.tags[handle.index as usize];
+        let before = tags.len();
+        tags.retain(|existing| existing != tag);
+
+        if tags.len() != before {
+            self.forget_tag(handle, tag);
+        }
+    }
+
+    pub fn has_tag(&self, handle: Handle, tag: &str) -> bool {
+        self.check(handle)
+            && self.tags[handle.index as usize].iter().any(|t| t == tag)
+    }
+
+    // Tags of the entity, in the order they were added
+    pub fn get_tags(&self, handle: Handle) -> &[String] {
+        if !self.check(handle) {
+            return &[];
+        }
+
+        &self.tags[handle.index as usize]
+    }
+
+    // Live entities with the tag, in the order they were tagged
+    pub fn tagged(&self, tag: &str) -> &[Handle] {
+        match self.tagged.get(tag) {
+            Some(handles) => handles,
+            None => &[],
+        }
+    }
+
+    fn forget_tag(&mut self, handle: Handle, tag: &str) {
+        let empty = match self.tagged.get_mut(tag) {
+            Some(handles) => {
+                handles.retain(|tagged| *tagged != handle);
+                handles.is_empty()
+            },
+
+            None => false,
+        };
+
+        if empty {
+            self.tagged.remove(tag);
+        }
+    }
+
+    // Index of a live handle, panicking otherwise
+    fn index(&self, handle: Handle) -> usize {
+        assert!(self.check(handle), "Stale entity handle {}", handle);
+        handle.index as usize
+    }
+
+    /* Scenes */
+
+    // Written so that loading reproduces the handles, the free list
+    // and the order of the tag lists
+    pub fn save_scene(&self, out: &mut scene::Writer) -> Result<(), Error> {
+        // Only their (unsaved) commands would spawn them
+        if !self.reserved.is_empty() {
+            return Err(
+                Error::Scene(
+                    "can't save with entities reserved but not spawned"
+                        .to_string()
+                )
+            );
+        }
+
+        for i in 0..self.generations.len() {
+            out.push(
+                scene::Line::new("entity")
+                    .usize(i)
+                    .u32(self.generations[i])
+                    .word(if self.alive[i] { "alive" } else { "dead" })
+            );
+        }
+
+        for index in &self.free {
+            out.push(scene::Line::new("free").u32(*index));
+        }
+
+        for handle in self.iter() {
+            if let Some(name) = self.get_name(handle) {
+                if !scene::is_word(name) {
+                    return Err(
+                        Error::Scene(format!("can't save name \"{}\"", name))
+                    );
+                }
+
+                out.push(scene::Line::new("name").handle(handle).word(name));
+            }
+        }
+
+        // Interleave the tags so that both each entity's tags and each
+        // tag's entities come back in order. The earliest remaining tagging
+        // is always at the front of both lists, so this never stalls.
+        let mut next_tag = vec![0; self.tags.len()];
+        let mut next_entity = std::collections::HashMap::new();
+
+        loop {
+            let mut progress = false;
+
+            for handle in self.iter() {
+                let i = handle.index as usize;
+
+                while let Some(tag) = self.tags[i].get(next_tag[i]) {
+                    let j = next_entity.entry(tag.as_str()).or_insert(0);
+
+                    if self.tagged[tag][*j] != handle {
+                        break;
+                    }
+
+                    if !scene::is_word(tag) {
+                        return Err(
+                            Error::Scene(format!("can't save tag \"{}\"", tag))
+                        );
+                    }
+
+                    out.push(scene::Line::new("tag").handle(handle).word(tag));
+
+                    next_tag[i] += 1;
+                    *j += 1;
+                    progress = true;
+                }
+            }
+
+            if !progress {
+                break;
+            }
+        }
+
+        debug_assert!(
+            self.iter().all(|handle| {
+                let i = handle.index as usize;
+                next_tag[i] == self.tags[i].len()
+            })
+        );
+
+        Ok(())
+    }
+
+    // Returns false for records belonging to other managers
+    pub fn load_scene(
+        &mut self,
+        kind: &str,
+        fields: &mut scene::Fields,
+    ) -> Result<bool, String> {
+        match kind {
+            "entity" => {
+                let index: usize = fields.parse()?;
+                let generation = fields.parse()?;
+
+                if index != self.generations.len() {
+                    return Err(format!("expected entity {}", index));
+                }
+
+                let alive = match fields.word()? {
+                    "alive" => true,
+                    "dead" => false,
+                    other => return Err(format!("invalid state \"{}\"", other)),
+                };
+
+                self.generations.push(generation);
+                self.alive.push(alive);
+                self.names.push(None);
+                self.tags.push(Vec::new());
+
+                if alive {
+                    self.count += 1;
+                }
+            },
+
+            "free" => {
+                let index: u32 = fields.parse()?;
+                let i = index as usize;
+
+                if i >= self.alive.len()
+                    || self.alive[i]
+                    || self.free.contains(&index)
+                {
+                    return Err(format!("can't free entity {}", index));
+                }
+
+                self.free.push(index);
+            },
+
+            "name" => {
+                let handle = self.live(fields.handle()?)?;
+                let name = fields.word()?;
+
+                if self.named.contains_key(name) {
+                    return Err(format!("duplicate name \"{}\"", name));
+                }
+
+                self.set_name(handle, name);
+            },
+
+            "tag" => {
+                let handle = self.live(fields.handle()?)?;
+                self.tag(handle, fields.word()?);
+            },
+
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    // Fails if a dead entity wasn't freed, as its index would never be reused
+    pub fn end_scene(&self) -> Result<(), String> {
+        let unfreed = (0..self.alive.len()).find(|&i| {
+            !self.alive[i] && !self.free.contains(&(i as u32))
+        });
+
+        match unfreed {
+            Some(i) => Err(format!("dead entity {} was not freed", i)),
+            None => Ok(()),
+        }
+    }
+
+    fn live(&self, handle: Handle) -> Result<Handle, String> {
+        if self.check(handle) {
+            Ok(handle)
+        } else {
+            Err(format!("no live entity {}", handle))
+        }
+    }
+
+    // Size of the index space (one more than the largest index handed out)
+    pub fn capacity(&self) -> usize {
+        self.generations.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::*;
+
+    #[test]
+    fn remove() {
+        let mut entities = Manager::new(4);
+
+        let a = entities.add();
+        let b = entities.add();
+        assert!(entities.check(a) && entities.check(b));
+        assert!(entities.count() == 2);
+
+        entities.remove(a);
+        entities.remove(a);
+        assert!(!entities.check(a));
+        assert!(entities.check(b));
+        assert!(entities.count() == 1);
+    }
+
+    #[test]
+    fn recycle() {
+        let mut entities = Manager::new(4);
+
+        let a = entities.add();
+        let b = entities.add();
+        entities.remove(a);
+
+        // Index reused with a new generation
+        let c = entities.add();
+        assert!(c.get_index() == a.get_index());
+        assert!(c.get_generation() != a.get_generation());
+        assert!(!entities.check(a));
+        assert!(entities.check(b) && entities.check(c));
+
+        // Spawning and despawning doesn't grow the index space
+        for _ in 0..1000 {
+            let projectile = entities.add();
+            entities.remove(projectile);
+        }
+
+        assert!(entities.capacity() == 3);
+        assert!(entities.count() == 2);
+    }
+
+    #[test]
+    fn names() {
+        let mut entities = Manager::new(4);
+
+        let arm = entities.add();
+        let leg = entities.add();
+
+        entities.set_name(arm, "left_arm");
+        entities.set_name(leg, "leg");
+        entities.set_name(leg, "left_leg");
+
+        assert!(entities.find("left_arm") == Some(arm));
+        assert!(entities.find("leg").is_none());
+        assert!(entities.get_name(leg) == Some("left_leg"));
+        assert!(entities.label(arm).to_string() == "left_arm (0:0)");
+
+        // Names are released with the entity
+        entities.remove(arm);
+        assert!(entities.find("left_arm").is_none());
+        assert!(entities.get_name(arm).is_none());
+
+        let new = entities.add();
+        assert!(entities.get_name(new).is_none());
+        assert!(entities.label(new).to_string() == "0:1");
+
+        entities.set_name(new, "left_arm");
+        assert!(entities.find("left_arm") == Some(new));
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_name() {
+        let mut entities = Manager::new(2);
+
+        let a = entities.add();
+        let b = entities.add();
+
+        entities.set_name(a, "player");
+        entities.set_name(b, "player");
+    }
+
+    #[test]
+    fn tags() {
+        let mut entities = Manager::new(4);
+
+        let a = entities.add();
+        let b = entities.add();
+        let c = entities.add();
+
+        entities.tag(b, "enemy");
+        entities.tag(a, "enemy");
+        entities.tag(a, "enemy");
+        entities.tag(a, "flying");
+        entities.set_name(a, "bat");
+
+        assert!(entities.tagged("enemy") == [b, a]);
+        assert!(entities.has_tag(a, "flying"));
+        assert!(!entities.has_tag(c, "enemy"));
+        assert!(entities.get_tags(a) == ["enemy", "flying"]);
+        assert!(
+            format!("{:?}", entities)
+                == "[bat (0:0) [enemy, flying], 1:0 [enemy], 2:0]"
+        );
+
+        entities.untag(a, "flying");
+        assert!(entities.tagged("flying").is_empty());
+
+        entities.remove(b);
+        assert!(entities.tagged("enemy") == [a]);
+        assert!(entities.get_tags(b).is_empty());
+    }
+
+    #[test]
+    fn iter() {
+        let mut entities = Manager::new(4);
+
+        let a = entities.add();
+        let b = entities.add();
+        let c = entities.add();
+        entities.remove(b);
+
+        assert!(entities.iter().collect::<Vec<_>>() == vec![a, c]);
+
+        let d = entities.add();
+        assert!(entities.iter().collect::<Vec<_>>() == vec![a, d, c]);
     }
 }
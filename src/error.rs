@@ -0,0 +1,41 @@
+extern crate voodoo as vd;
+
+use std;
+
+// Engine-wide error, returned to the host application
+#[derive(Debug)]
+pub enum Error {
+    Window(String),
+    Render(vd::Error),
+    Config(String),
+    Asset(String),
+    Scene(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Error::Window(ref e) => write!(out, "window error: {}", e),
+            Error::Render(ref e) => write!(out, "render error: {}", e),
+            Error::Config(ref e) => write!(out, "config error: {}", e),
+            Error::Asset(ref e) => write!(out, "asset error: {}", e),
+            Error::Scene(ref e) => write!(out, "scene error: {}", e),
+            Error::Io(ref e) => write!(out, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<vd::Error> for Error {
+    fn from(e: vd::Error) -> Error {
+        Error::Render(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
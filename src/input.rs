@@ -0,0 +1,702 @@
+extern crate voodoo_winit as vdw;
+
+use std;
+use alg;
+use config;
+
+use error::Error;
+
+pub use self::vdw::winit::VirtualKeyCode as Key;
+pub use self::vdw::winit::MouseButton as Button;
+
+// Window-independent input event (can be synthesized for testing)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    KeyDown(Key),
+    KeyUp(Key),
+    ButtonDown(Button),
+    ButtonUp(Button),
+    CursorMoved(alg::Vec2), // Window coordinates
+    Scroll(alg::Vec2),
+}
+
+impl Event {
+    // Translate a winit window event, if it carries input
+    pub fn from_window(event: &vdw::winit::WindowEvent) -> Option<Event> {
+        use self::vdw::winit::ElementState;
+        use self::vdw::winit::MouseScrollDelta;
+        use self::vdw::winit::WindowEvent;
+
+        match *event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                input.virtual_keycode.map(|key| match input.state {
+                    ElementState::Pressed => Event::KeyDown(key),
+                    ElementState::Released => Event::KeyUp(key),
+                })
+            },
+
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => Some(Event::ButtonDown(button)),
+                    ElementState::Released => Some(Event::ButtonUp(button)),
+                }
+            },
+
+            WindowEvent::MouseMoved { position, .. } => Some(
+                Event::CursorMoved(
+                    alg::Vec2::new(position.0 as f32, position.1 as f32)
+                )
+            ),
+
+            // Units depend on the device (lines or pixels)
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y)
+                    | MouseScrollDelta::PixelDelta(x, y)
+                    => Some(Event::Scroll(alg::Vec2::new(x, y))),
+            },
+
+            _ => None,
+        }
+    }
+}
+
+// Key names accepted in bindings and replays (matching the variant names)
+macro_rules! parse_key {
+    ($name: expr, $($key: ident),*) => {
+        match $name {
+            $(stringify!($key) => Some(Key::$key),)*
+            _ => None,
+        }
+    }
+}
+
+// Physical input that can be bound to an action
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    Key(Key),
+    Button(Button),
+}
+
+impl std::str::FromStr for Source {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Source, Self::Err> {
+        let value = value.trim();
+
+        match value {
+            "MouseLeft" => return Ok(Source::Button(Button::Left)),
+            "MouseRight" => return Ok(Source::Button(Button::Right)),
+            "MouseMiddle" => return Ok(Source::Button(Button::Middle)),
+            _ => (),
+        }
+
+        // Other mouse buttons, by number (e.g. "Mouse4")
+        if let Some(number) = value.strip_prefix("Mouse") {
+            if let Ok(number) = number.parse() {
+                return Ok(Source::Button(Button::Other(number)));
+            }
+        }
+
+        parse_key!(
+            value,
+            Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, A, B, C,
+            D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+            Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14,
+            F15, Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown,
+            PageUp, Left, Up, Right, Down, Back, Return, Space, Compose,
+            Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5,
+            Numpad6, Numpad7, Numpad8, Numpad9, AbntC1, AbntC2, Add, Apostrophe,
+            Apps, At, Ax, Backslash, Calculator, Capital, Colon, Comma, Convert,
+            Decimal, Divide, Equals, Grave, Kana, Kanji, LAlt, LBracket,
+            LControl, LMenu, LShift, LWin, Mail, MediaSelect, MediaStop, Minus,
+            Multiply, Mute, MyComputer, NavigateForward, NavigateBackward,
+            NextTrack, NoConvert, NumpadComma, NumpadEnter, NumpadEquals,
+            OEM102, Period, PlayPause, Power, PrevTrack, RAlt, RBracket,
+            RControl, RMenu, RShift, RWin, Semicolon, Slash, Sleep, Stop,
+            Subtract, Sysrq, Tab, Underline, Unlabeled, VolumeDown, VolumeUp,
+            Wake, WebBack, WebFavorites, WebForward, WebHome, WebRefresh,
+            WebSearch, WebStop, Yen
+        ).map(Source::Key)
+            .ok_or_else(|| format!("unknown key or button \"{}\"", value))
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Source::Key(key) => write!(out, "{:?}", key),
+            Source::Button(Button::Left) => write!(out, "MouseLeft"),
+            Source::Button(Button::Right) => write!(out, "MouseRight"),
+            Source::Button(Button::Middle) => write!(out, "MouseMiddle"),
+            Source::Button(Button::Other(n)) => write!(out, "Mouse{}", n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Binding {
+    pub source: Source,
+    pub scale: f32, // Contribution when queried as an axis
+}
+
+impl std::str::FromStr for Binding {
+    type Err = String;
+
+    // "Space", "+D", or "-A"
+    fn from_str(value: &str) -> Result<Binding, Self::Err> {
+        let value = value.trim();
+
+        let (scale, name) = if let Some(name) = value.strip_prefix('-') {
+            (-1., name)
+        } else if let Some(name) = value.strip_prefix('+') {
+            (1., name)
+        } else {
+            (1., value)
+        };
+
+        Ok(
+            Binding {
+                source: name.parse()?,
+                scale,
+            }
+        )
+    }
+}
+
+// Named actions (and axes), each bound to any number of sources
+pub struct Bindings {
+    actions: std::collections::HashMap<String, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings {
+            actions: std::collections::HashMap::new(),
+        }
+    }
+
+    // Load the [bindings] section of the engine config
+    pub fn from_config() -> Result<Bindings, Error> {
+        let mut bindings = Bindings::new();
+
+        for (action, value) in config::section("bindings")? {
+            bindings.parse(&action, &value).map_err(|e| {
+                Error::Config(
+                    format!("binding \"{}\" in [bindings]: {}", action, e)
+                )
+            })?;
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.actions.entry(action.to_string())
+            .or_default()
+            .push(binding);
+    }
+
+    // Bind a comma-separated list of sources, e.g. "-A, +D, -Left, +Right"
+    pub fn parse(&mut self, action: &str, value: &str) -> Result<(), String> {
+        for binding in value.split(',') {
+            let binding = binding.parse()?;
+            self.bind(action, binding);
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, action: &str) -> &[Binding] {
+        self.actions.get(action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings::new()
+    }
+}
+
+// Transitions since the last reset
+struct Edges {
+    keys_pressed:     std::collections::HashSet<Key>,
+    keys_released:    std::collections::HashSet<Key>,
+    buttons_pressed:  std::collections::HashSet<Button>,
+    buttons_released: std::collections::HashSet<Button>,
+    cursor_delta:     alg::Vec2,
+    scroll:           alg::Vec2,
+
+    // Held at the last reset, for action edges
+    keys_held:        std::collections::HashSet<Key>,
+    buttons_held:     std::collections::HashSet<Button>,
+}
+
+impl Edges {
+    fn new() -> Edges {
+        Edges {
+            keys_pressed:     std::collections::HashSet::new(),
+            keys_released:    std::collections::HashSet::new(),
+            buttons_pressed:  std::collections::HashSet::new(),
+            buttons_released: std::collections::HashSet::new(),
+            cursor_delta:     alg::Vec2::zero(),
+            scroll:           alg::Vec2::zero(),
+            keys_held:        std::collections::HashSet::new(),
+            buttons_held:     std::collections::HashSet::new(),
+        }
+    }
+
+    fn clear(
+        &mut self,
+        keys: &std::collections::HashSet<Key>,
+        buttons: &std::collections::HashSet<Button>,
+    ) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.cursor_delta = alg::Vec2::zero();
+        self.scroll = alg::Vec2::zero();
+        self.keys_held.clone_from(keys);
+        self.buttons_held.clone_from(buttons);
+    }
+}
+
+/* Edges are tracked separately for frames and fixed steps, so that a press
+ * is seen by exactly one fixed update, even if a frame runs several (or none).
+ * The engine switches between the two views around the fixed update loop.
+ */
+
+pub struct Manager {
+    keys:    std::collections::HashSet<Key>,
+    buttons: std::collections::HashSet<Button>,
+    cursor:  Option<alg::Vec2>,
+    events:  Vec<Event>, // Handled this frame, in order
+
+    bindings: Bindings,
+
+    frame: Edges,
+    fixed: Edges,
+    in_fixed: bool,
+}
+
+impl Manager {
+    pub fn new() -> Manager {
+        Manager::with_bindings(Bindings::new())
+    }
+
+    pub fn with_bindings(bindings: Bindings) -> Manager {
+        Manager {
+            keys:    std::collections::HashSet::new(),
+            buttons: std::collections::HashSet::new(),
+            cursor:  None,
+            events:  Vec::new(),
+
+            bindings,
+
+            frame: Edges::new(),
+            fixed: Edges::new(),
+            in_fixed: false,
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) {
+        self.events.push(event);
+
+        match event {
+            Event::KeyDown(key) => {
+                // Ignore key repeat
+                if self.keys.insert(key) {
+                    self.frame.keys_pressed.insert(key);
+                    self.fixed.keys_pressed.insert(key);
+                }
+            },
+
+            Event::KeyUp(key) => {
+                if self.keys.remove(&key) {
+                    self.frame.keys_released.insert(key);
+                    self.fixed.keys_released.insert(key);
+                }
+            },
+
+            Event::ButtonDown(button) => {
+                if self.buttons.insert(button) {
+                    self.frame.buttons_pressed.insert(button);
+                    self.fixed.buttons_pressed.insert(button);
+                }
+            },
+
+            Event::ButtonUp(button) => {
+                if self.buttons.remove(&button) {
+                    self.frame.buttons_released.insert(button);
+                    self.fixed.buttons_released.insert(button);
+                }
+            },
+
+            Event::CursorMoved(position) => {
+                // The first position has no delta
+                if let Some(last) = self.cursor {
+                    let delta = position - last;
+                    self.frame.cursor_delta = self.frame.cursor_delta + delta;
+                    self.fixed.cursor_delta = self.fixed.cursor_delta + delta;
+                }
+
+                self.cursor = Some(position);
+            },
+
+            Event::Scroll(delta) => {
+                self.frame.scroll = self.frame.scroll + delta;
+                self.fixed.scroll = self.fixed.scroll + delta;
+            },
+        }
+    }
+
+    // Release everything that is held (e.g. when the window loses focus)
+    pub fn release_all(&mut self) {
+        let keys: Vec<Key> = self.keys.iter().cloned().collect();
+        let buttons: Vec<Button> = self.buttons.iter().cloned().collect();
+
+        for key in keys {
+            self.handle(Event::KeyUp(key));
+        }
+
+        for button in buttons {
+            self.handle(Event::ButtonUp(button));
+        }
+    }
+
+    /* Engine hooks */
+
+    // Called before polling events each frame
+    pub fn begin_frame(&mut self) {
+        self.frame.clear(&self.keys, &self.buttons);
+        self.events.clear();
+    }
+
+    // Called around the fixed update loop
+    pub fn set_fixed(&mut self, in_fixed: bool) {
+        self.in_fixed = in_fixed;
+    }
+
+    // Called after every fixed step
+    pub fn end_fixed(&mut self) {
+        self.fixed.clear(&self.keys, &self.buttons);
+    }
+
+    /* Queries */
+
+    #[inline]
+    fn edges(&self) -> &Edges {
+        if self.in_fixed { &self.fixed } else { &self.frame }
+    }
+
+    pub fn key_down(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: Key) -> bool {
+        self.edges().keys_pressed.contains(&key)
+    }
+
+    pub fn key_released(&self, key: Key) -> bool {
+        self.edges().keys_released.contains(&key)
+    }
+
+    pub fn button_down(&self, button: Button) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: Button) -> bool {
+        self.edges().buttons_pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: Button) -> bool {
+        self.edges().buttons_released.contains(&button)
+    }
+
+    // Defaults to the origin before the cursor has moved
+    pub fn cursor(&self) -> alg::Vec2 {
+        self.cursor.unwrap_or(alg::Vec2::zero())
+    }
+
+    pub fn cursor_delta(&self) -> alg::Vec2 {
+        self.edges().cursor_delta
+    }
+
+    pub fn scroll(&self) -> alg::Vec2 {
+        self.edges().scroll
+    }
+
+    // Every event handled this frame, including key repeats
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /* Actions */
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    fn source_down(&self, source: Source) -> bool {
+        match source {
+            Source::Key(key) => self.key_down(key),
+            Source::Button(button) => self.button_down(button),
+        }
+    }
+
+    fn source_pressed(&self, source: Source) -> bool {
+        match source {
+            Source::Key(key) => self.key_pressed(key),
+            Source::Button(button) => self.button_pressed(button),
+        }
+    }
+
+    fn source_released(&self, source: Source) -> bool {
+        match source {
+            Source::Key(key) => self.key_released(key),
+            Source::Button(button) => self.button_released(button),
+        }
+    }
+
+    // Whether the source was held when the edges were last reset
+    fn source_held(&self, source: Source) -> bool {
+        match source {
+            Source::Key(key) => self.edges().keys_held.contains(&key),
+            Source::Button(button) => {
+                self.edges().buttons_held.contains(&button)
+            },
+        }
+    }
+
+    // True while any bound source is held (unknown actions are never down)
+    pub fn action(&self, action: &str) -> bool {
+        self.bindings.get(action).iter()
+            .any(|binding| self.source_down(binding.source))
+    }
+
+    // True once the first source is pressed; pressing another source
+    // while the action is already held doesn't count
+    pub fn action_pressed(&self, action: &str) -> bool {
+        let bindings = self.bindings.get(action);
+
+        !bindings.iter().any(|binding| self.source_held(binding.source))
+            && bindings.iter().any(|binding| {
+                self.source_pressed(binding.source)
+            })
+    }
+
+    // True once the last held source is released
+    pub fn action_released(&self, action: &str) -> bool {
+        !self.action(action) && self.bindings.get(action).iter()
+            .any(|binding| self.source_released(binding.source))
+    }
+
+    // Sum of held sources, clamped to [-1, 1]
+    pub fn axis(&self, action: &str) -> f32 {
+        let value: f32 = self.bindings.get(action).iter()
+            .filter(|binding| self.source_down(binding.source))
+            .map(|binding| binding.scale)
+            .sum();
+
+        value.clamp(-1., 1.)
+    }
+}
+
+impl Default for Manager {
+    fn default() -> Manager {
+        Manager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use input::*;
+
+    #[test]
+    fn press_release() {
+        let mut input = Manager::new();
+
+        input.begin_frame();
+        input.handle(Event::KeyDown(Key::Space));
+
+        assert!(input.key_down(Key::Space));
+        assert!(input.key_pressed(Key::Space));
+        assert!(!input.key_released(Key::Space));
+
+        // Held, and repeated by the OS
+        input.begin_frame();
+        input.handle(Event::KeyDown(Key::Space));
+
+        assert!(input.key_down(Key::Space));
+        assert!(!input.key_pressed(Key::Space));
+
+        input.begin_frame();
+        input.handle(Event::KeyUp(Key::Space));
+
+        assert!(!input.key_down(Key::Space));
+        assert!(input.key_released(Key::Space));
+
+        input.begin_frame();
+        assert!(!input.key_released(Key::Space));
+    }
+
+    #[test]
+    fn buttons() {
+        let mut input = Manager::new();
+
+        input.handle(Event::ButtonDown(Button::Left));
+        assert!(input.button_down(Button::Left));
+        assert!(input.button_pressed(Button::Left));
+        assert!(!input.button_down(Button::Right));
+
+        input.release_all();
+        assert!(!input.button_down(Button::Left));
+        assert!(input.button_released(Button::Left));
+    }
+
+    #[test]
+    fn cursor_and_scroll() {
+        let mut input = Manager::new();
+
+        input.handle(Event::CursorMoved(alg::Vec2::new(10., 10.)));
+        assert!(input.cursor() == alg::Vec2::new(10., 10.));
+        assert!(input.cursor_delta() == alg::Vec2::zero());
+
+        input.handle(Event::CursorMoved(alg::Vec2::new(12., 7.)));
+        input.handle(Event::CursorMoved(alg::Vec2::new(13., 7.)));
+        assert!(input.cursor_delta() == alg::Vec2::new(3., -3.));
+
+        input.handle(Event::Scroll(alg::Vec2::new(0., 1.)));
+        input.handle(Event::Scroll(alg::Vec2::new(0., 2.)));
+        assert!(input.scroll() == alg::Vec2::new(0., 3.));
+
+        input.begin_frame();
+        assert!(input.cursor() == alg::Vec2::new(13., 7.));
+        assert!(input.cursor_delta() == alg::Vec2::zero());
+        assert!(input.scroll() == alg::Vec2::zero());
+    }
+
+    #[test]
+    fn fixed_edges() {
+        let mut input = Manager::new();
+
+        input.begin_frame();
+        input.handle(Event::KeyDown(Key::W));
+
+        // Two fixed steps in one frame: only the first sees the press
+        input.set_fixed(true);
+        assert!(input.key_pressed(Key::W));
+        input.end_fixed();
+        assert!(!input.key_pressed(Key::W));
+        assert!(input.key_down(Key::W));
+        input.end_fixed();
+        input.set_fixed(false);
+
+        assert!(input.key_pressed(Key::W));
+
+        // No fixed steps this frame: the press carries over
+        input.begin_frame();
+        input.handle(Event::KeyUp(Key::W));
+        input.begin_frame();
+
+        input.set_fixed(true);
+        assert!(input.key_released(Key::W));
+        input.end_fixed();
+        input.set_fixed(false);
+    }
+
+    #[test]
+    fn parse_bindings() {
+        let mut bindings = Bindings::new();
+
+        bindings.parse("move_x", "-A, +D, -Left").unwrap();
+        bindings.parse("fire", "MouseLeft").unwrap();
+
+        assert!(bindings.get("move_x").len() == 3);
+        assert!(
+            bindings.get("move_x")[0]
+                == Binding { source: Source::Key(Key::A), scale: -1. }
+        );
+        assert!(
+            bindings.get("fire")[0].source == Source::Button(Button::Left)
+        );
+        assert!(bindings.get("jump").is_empty());
+
+        assert!(bindings.parse("jump", "Spacebar").is_err());
+        assert!(bindings.parse("jump", "MouseUp").is_err());
+        assert!(bindings.parse("jump", "").is_err());
+    }
+
+    #[test]
+    fn source_names() {
+        let sources = [
+            Source::Key(Key::Numpad7),
+            Source::Key(Key::Yen),
+            Source::Button(Button::Middle),
+            Source::Button(Button::Other(4)),
+        ];
+
+        for source in &sources {
+            assert!(source.to_string().parse() == Ok(*source));
+        }
+    }
+
+    #[test]
+    fn actions() {
+        let mut bindings = Bindings::new();
+        bindings.parse("move_x", "-A, +D, -Left, +Right").unwrap();
+        bindings.parse("jump", "Space, MouseLeft").unwrap();
+
+        let mut input = Manager::with_bindings(bindings);
+        assert!(input.axis("move_x") == 0.);
+        assert!(!input.action("jump"));
+        assert!(!input.action("unbound"));
+
+        input.handle(Event::KeyDown(Key::D));
+        input.handle(Event::KeyDown(Key::Right));
+        assert!(input.axis("move_x") == 1.); // Clamped
+
+        input.handle(Event::KeyDown(Key::A));
+        assert!(input.axis("move_x") == 1.);
+        input.handle(Event::KeyUp(Key::Right));
+        assert!(input.axis("move_x") == 0.);
+
+        input.handle(Event::KeyDown(Key::Space));
+        input.handle(Event::ButtonDown(Button::Left));
+        assert!(input.action("jump"));
+        assert!(input.action_pressed("jump"));
+
+        // Still held by the mouse
+        input.begin_frame();
+        input.handle(Event::KeyUp(Key::Space));
+        assert!(input.action("jump"));
+        assert!(!input.action_released("jump"));
+
+        input.handle(Event::ButtonUp(Button::Left));
+        assert!(input.action_released("jump"));
+
+        // A second source joining a hold is not another press
+        input.begin_frame();
+        input.handle(Event::KeyDown(Key::Space));
+        assert!(input.action_pressed("jump"));
+
+        input.begin_frame();
+        input.handle(Event::ButtonDown(Button::Left));
+        assert!(input.action("jump"));
+        assert!(!input.action_pressed("jump"));
+
+        // Only the state at the start of the frame counts
+        input.begin_frame();
+        input.handle(Event::ButtonUp(Button::Left));
+        input.handle(Event::KeyUp(Key::Space));
+        input.handle(Event::KeyDown(Key::Space));
+        assert!(!input.action_pressed("jump"));
+    }
+}
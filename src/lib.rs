@@ -10,18 +10,43 @@ pub mod render;
 pub mod graphics;
 pub mod entity;
 pub mod components;
+pub mod command;
 pub mod config;
 pub mod debug;
+pub mod clock;
+pub mod input;
+pub mod control;
+pub mod window;
+pub mod profiler;
+pub mod replay;
+pub mod scene;
+mod error;
 mod statics;
 mod util;
 
-const FIXED_DT: f32 = 1. / 100.;
+pub use error::Error;
+
+use clock::Clock;
+use render::Renderer;
+
+const DEFAULT_FIXED_RATE: u32 = 100;
+const DEFAULT_MAX_SUBSTEPS: u32 = 8;
+
+// Screen size reported to the game when running without a window
+const HEADLESS_WIDTH: u32 = 1280;
+const HEADLESS_HEIGHT: u32 = 720;
+
+// Most recent frames kept by the headless renderer
+const HEADLESS_FRAMES: usize = 256;
 
 #[derive(Clone, Copy)]
 pub struct Metadata {
     pub frame: u32,
     pub fixed_frame: u32,
     pub fps: u32,
+    pub alpha: f32, // Progress between the last and next fixed step (0 - 1)
+    pub timings: profiler::Timings, // Previous frame
+    pub profile: profiler::Summary, // Rolling, over recent frames
 }
 
 impl Metadata {
@@ -30,7 +55,60 @@ impl Metadata {
             frame: 0,
             fixed_frame: 0,
             fps: 0,
+            alpha: 0.,
+            timings: profiler::Timings::new(),
+            profile: profiler::Summary::new(),
+        }
+    }
+}
+
+// Fixed update rate, and a cap on the fixed updates run in a single frame
+// (prevents long frames from snowballing into longer ones)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timestep {
+    pub rate: u32, // Fixed updates per second
+    pub max_substeps: u32,
+}
+
+impl Timestep {
+    pub fn new(rate: u32, max_substeps: u32) -> Timestep {
+        assert!(rate > 0);
+        assert!(max_substeps > 0);
+
+        Timestep {
+            rate,
+            max_substeps,
+        }
+    }
+
+    // Load from the engine config, falling back on defaults
+    pub fn from_config() -> Result<Timestep, Error> {
+        let rate = config::get("settings", "fixed_rate")?
+            .unwrap_or(DEFAULT_FIXED_RATE);
+
+        let max_substeps = config::get("settings", "max_substeps")?
+            .unwrap_or(DEFAULT_MAX_SUBSTEPS);
+
+        if rate == 0 || max_substeps == 0 {
+            return Err(
+                Error::Config(
+                    "fixed_rate and max_substeps must be positive".to_string()
+                )
+            );
         }
+
+        Ok(Timestep::new(rate, max_substeps))
+    }
+
+    #[inline]
+    pub fn delta(self) -> f32 {
+        1. / self.rate as f32
+    }
+}
+
+impl Default for Timestep {
+    fn default() -> Timestep {
+        Timestep::new(DEFAULT_FIXED_RATE, DEFAULT_MAX_SUBSTEPS)
     }
 }
 
@@ -50,6 +128,8 @@ pub trait Update {
         metadata: Metadata,
         screen_height: u32,
         screen_width:  u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
@@ -64,50 +144,93 @@ pub trait FixedUpdate {
         metadata: Metadata,
         screen_heigh: u32,
         screen_width: u32,
+        input: &input::Manager,
+        control: &mut control::Handler,
         entities: &mut entity::Manager,
         components: &mut components::Container,
         debug: &mut debug::Handler,
     );
 }
 
-pub fn go<T>(model_data: Vec<render::ModelData>, mut game: T)
+// Run the game with the window settings from the engine config
+pub fn go<T>(
+    model_data: Vec<render::ModelData>,
+    game: T,
+) -> Result<(), Error>
 where
     T: Start + Update + FixedUpdate
 {
-    // Initialize window
-    let (events, window) = init_window();
+    go_with_window(model_data, game, window::Settings::from_config()?)
+}
 
-    // Initialize rendering engine
-    let mut context = match render::Context::new(&window, model_data) {
-        Ok(mut context) => context,
-        Err(e) => panic!("Could not create Vulkan context: {}", e)
-    };
+pub fn go_with_window<T>(
+    model_data: Vec<render::ModelData>,
+    mut game: T,
+    settings: window::Settings,
+) -> Result<(), Error>
+where
+    T: Start + Update + FixedUpdate
+{
+    let timestep = Timestep::from_config()?;
+    let limit = clock::FrameLimit::from_config()?;
+    let bindings = input::Bindings::from_config()?;
+    let mut profiler = profiler::Profiler::from_config()?;
 
-    let instances = render::Instances::new(context.models.len(), None);
+    // Optionally record the run for replay
+    let record = config::get::<String>("replay", "record")?;
 
-    // Create entities container
-    let mut entities = entity::Manager::new(1);
+    let present_mode = settings.present_mode.unwrap_or(
+        if limit == clock::FrameLimit::Vsync {
+            window::PresentMode::Fifo
+        } else {
+            window::PresentMode::Mailbox
+        }
+    );
 
-    // Initialize core components
-    let mut components = components::Container {
-        transforms:  components::transform::Manager::new(1),
-        draws:       components::draw::Manager::new(1, instances),
-        rigidbodies: components::rigidbody::Manager::new(1),
-        softbodies:  components::softbody::Manager::new(1, 1, 1),
+    // Initialize window
+    let (events, window) = init_window(&settings)?;
+
+    // Initialize rendering engine
+    let mut context = render::Context::new(
+        &window,
+        model_data,
+        present_mode,
+    )?;
+
+    let recording = match record {
+        Some(path) => Some(
+            replay::Writer::create(&path, timestep, context.extent())?
+        ),
+        None => None,
     };
 
+    // Create entities container and initialize core components
+    let (mut entities, mut components) = init_components(
+        context.models.len(),
+        timestep,
+    );
+
     // Initialize debug struct
     let mut debug = debug::Handler::new();
 
+    let mut input = input::Manager::with_bindings(bindings);
+    let mut control = control::Handler::new();
+
     // Start game
     game.start(&mut entities, &mut components);
 
     // Initiate update loop
-    begin_update(
-        game,
+    let result = begin_update(
+        &mut game,
         &window,
         events,
+        timestep,
+        limit,
         &mut context,
+        &mut input,
+        &mut control,
+        &mut profiler,
+        recording,
         &mut entities,
         &mut components,
         &mut debug,
@@ -115,51 +238,285 @@ where
 
     // Synchronize before exit
     context.device.wait_idle();
+
+    result
+}
+
+// Engine state after a headless run, returned for inspection
+pub struct Headless {
+    pub entities:   entity::Manager,
+    pub components: components::Container,
+    pub metadata:   Metadata,
+    pub renderer:   render::Recorder, // Keeps the most recent frames
+}
+
+// Run the game without a window or Vulkan context, for a fixed number of
+// frames. Time is simulated: every frame advances the clock by frame_time
+// seconds, then the limiter pads it out to the target frame rate.
+// Vsync is treated as unlimited, since there is no display to wait on.
+pub fn go_headless<T>(
+    model_data: Vec<render::ModelData>,
+    game:       &mut T,
+    frames:     u32,
+    frame_time: f64,
+    timestep:   Timestep,
+    limit:      clock::FrameLimit,
+) -> Result<Headless, Error>
+where
+    T: Start + Update + FixedUpdate
+{
+    debug_assert!(frame_time >= 0.);
+
+    let mut clock = clock::SimulatedClock::new();
+    let mut limiter = clock::Limiter::new(limit);
+
+    let bindings = input::Bindings::new(); // No input to bind
+
+    run_headless(model_data, game, timestep, bindings, |frame, _, _| {
+        if frame == frames {
+            return None;
+        }
+
+        // Simulate the last frame's workload, then pace it
+        if frame > 0 {
+            clock.advance(frame_time);
+            limiter.wait(&mut clock);
+        }
+
+        Some(clock.now())
+    })
+}
+
+// Play back a recording without a window. Given the same game and
+// bindings (e.g. input::Bindings::from_config()), the simulation is
+// bit-identical to the recorded run.
+pub fn go_replay<T>(
+    model_data: Vec<render::ModelData>,
+    game:       &mut T,
+    replay:     &replay::Replay,
+    bindings:   input::Bindings,
+) -> Result<Headless, Error>
+where
+    T: Start + Update + FixedUpdate
+{
+    let timestep = replay.timestep;
+
+    run_headless(model_data, game, timestep, bindings, |i, input, renderer| {
+        if i == 0 {
+            renderer.resize(replay.size.0, replay.size.1);
+        }
+
+        replay.frames.get(i as usize).map(|frame| {
+            if let Some((width, height)) = frame.resize {
+                renderer.resize(width, height);
+            }
+
+            for event in &frame.events {
+                input.handle(*event);
+            }
+
+            frame.time
+        })
+    })
 }
 
-fn init_window() -> (vdw::winit::EventsLoop, vdw::winit::Window) {
+// Headless engine loop. The source is called at the start of every frame
+// to feed input (and resize the screen), and returns the frame's time
+// (or None to stop).
+fn run_headless<T, S>(
+    model_data: Vec<render::ModelData>,
+    game:       &mut T,
+    timestep:   Timestep,
+    bindings:   input::Bindings,
+    mut source: S,
+) -> Result<Headless, Error>
+where
+    T: Start + Update + FixedUpdate,
+    S: FnMut(u32, &mut input::Manager, &mut render::Recorder) -> Option<f64>,
+{
+    let (mut entities, mut components) = init_components(
+        model_data.len(),
+        timestep,
+    );
+    let mut debug = debug::Handler::new();
+    let mut input = input::Manager::with_bindings(bindings);
+    let mut control = control::Handler::new();
+    let mut profiler = profiler::Profiler::default();
+
+    // Record frames in place of drawing them
+    let mut renderer = render::Recorder::new(HEADLESS_WIDTH, HEADLESS_HEIGHT)
+        .with_limit(HEADLESS_FRAMES);
+
+    // Start game
+    game.start(&mut entities, &mut components);
+
+    let mut last_time = 0f64;
+    let mut accumulator = 0f32; // Fixed-framerate accumulator
+    let mut last_updated = 0f64;
+    let mut last_frame = 0u32;
+
+    let mut metadata = Metadata::new();
+
+    for frame in 0.. {
+        if control.exiting() { break; }
+
+        input.begin_frame();
+
+        let time = match source(frame, &mut input, &mut renderer) {
+            Some(time) => time,
+            None => break,
+        };
+
+        let (width, height) = renderer.extent();
+
+        let delta = time - last_time;
+        last_time = time;
+
+        let shared_ubo = step(
+            game,
+            time,
+            delta,
+            timestep,
+            &mut accumulator,
+            &mut metadata,
+            height,
+            width,
+            &mut input,
+            &mut control,
+            &mut profiler,
+            &mut entities,
+            &mut components,
+            &mut debug,
+        );
+
+        render(
+            &mut renderer,
+            &mut profiler,
+            &components,
+            &debug,
+            shared_ubo,
+        )?;
+
+        finish_frame(&mut metadata, &mut profiler, true)?;
+
+        if time - last_updated >= 1. {
+            // Frames per second (simulated)
+            metadata.fps = metadata.frame - last_frame;
+            last_frame = metadata.frame;
+            last_updated = time;
+        }
+    }
+
+    Ok(
+        Headless {
+            entities,
+            components,
+            metadata,
+            renderer,
+        }
+    )
+}
+
+fn init_components(
+    model_count: usize,
+    timestep:    Timestep,
+) -> (entity::Manager, components::Container) {
+    let instances = render::Instances::new(model_count, None);
+
+    // Create entities container
+    let entities = entity::Manager::new(1);
+
+    // Initialize core components
+    let mut components = components::Container::new(
+        components::transform::Manager::new(1),
+        components::draw::Manager::new(1, instances),
+        components::rigidbody::Manager::new(1),
+        components::softbody::Manager::new(1, 1, 1),
+    );
+
+    components.softbodies.set_timestep(timestep.delta());
+
+    (entities, components)
+}
+
+fn init_window(settings: &window::Settings) -> Result<
+    (vdw::winit::EventsLoop, vdw::winit::Window),
+    Error,
+> {
     let events = vdw::winit::EventsLoop::new();
 
-    let window = vdw::winit::WindowBuilder::new()
-        .with_title(statics::TITLE)
-        .build(&events);
+    let mut builder = vdw::winit::WindowBuilder::new()
+        .with_title(settings.title.clone())
+        .with_dimensions(settings.width, settings.height);
+
+    match settings.mode {
+        window::Mode::Windowed => (),
+
+        window::Mode::Fullscreen => {
+            builder = builder.with_fullscreen(
+                Some(events.get_primary_monitor())
+            );
+        },
+
+        window::Mode::Borderless => {
+            let (width, height) = events.get_primary_monitor()
+                .get_dimensions();
 
-    if let Err(e) = window {
-        panic!("{}", e);
+            builder = builder
+                .with_decorations(false)
+                .with_dimensions(width, height);
+        },
     }
 
-    (events, window.unwrap())
+    // Pin the size (winit has no direct option)
+    if !settings.resizable && settings.mode == window::Mode::Windowed {
+        builder = builder
+            .with_min_dimensions(settings.width, settings.height)
+            .with_max_dimensions(settings.width, settings.height);
+    }
+
+    let window = builder.build(&events)
+        .map_err(|e| Error::Window(e.to_string()))?;
+
+    Ok((events, window))
 }
 
 fn begin_update<T>(
-    mut game:   T,
+    game:       &mut T,
     window:     &vdw::winit::Window,
     mut events: vdw::winit::EventsLoop,
+    timestep:   Timestep,
+    limit:      clock::FrameLimit,
     context:    &mut render::Context,
+    input:      &mut input::Manager,
+    control:    &mut control::Handler,
+    profiler:   &mut profiler::Profiler,
+    mut recording: Option<replay::Writer>,
     entities:   &mut entity::Manager,
     components: &mut components::Container,
     debug:      &mut debug::Handler,
-) where
+) -> Result<(), Error>
+where
     T: Start + Update + FixedUpdate
 {
     let mut running = true;
+    let mut failure = None;
+
+    let mut clock = clock::RealClock::new();
+    let mut limiter = clock::Limiter::new(limit);
 
-    let start = std::time::Instant::now();
     let mut last_time = 0f64;
     let mut accumulator = 0f32; // Fixed-framerate accumulator
-    let mut last_updated = std::time::Instant::now();
+    let mut last_updated = 0f64;
     let mut last_frame = 0u32;
 
     let mut metadata = Metadata::new();
 
-    let config_data = &config::ENGINE_CONFIG;
-    let target_fps = config_data
-        .section(Some("settings")).unwrap()
-        .get("fps").unwrap();
-
-    println!("Target frames per second: {}", target_fps);
+    println!("Target frames per second: {}", limit);
 
     loop {
+        input.begin_frame();
+
         // Handle window events
         events.poll_events(|event| {
             match event {
@@ -173,7 +530,7 @@ fn begin_update<T>(
                     if width == 0 || height == 0 { return; }
 
                     if let Err(e) = context.refresh_swapchain(width, height) {
-                        panic!("{}", e);
+                        failure = Some(e);
                     }
                 },
 
@@ -185,83 +542,65 @@ fn begin_update<T>(
                     running = false;
                 },
 
+                // Don't leave keys held while the window can't see them
+                vdw::winit::Event::WindowEvent {
+                    event: vdw::winit::WindowEvent::Focused(false),
+                    ..
+                } => {
+                    input.release_all();
+                },
+
+                vdw::winit::Event::WindowEvent { event, .. } => {
+                    if let Some(event) = input::Event::from_window(&event) {
+                        input.handle(event);
+                    }
+                },
+
                 _ => ()
             }
         });
 
-        if !running { break; }
-
-        /* Time calculations */
+        // Irrecoverable error
+        if let Some(e) = failure {
+            return Err(Error::from(e));
+        }
 
-        let now = std::time::Instant::now();
-        let duration = now.duration_since(start);
+        if !running || control.exiting() { break; }
 
-        let time = duration.as_secs() as f64
-            + (duration.subsec_nanos() as f64 / 1000000000.);
+        /* Time calculations */
 
+        let time = clock.now();
         let delta = time - last_time;
         last_time = time;
 
-        // Update scene data
-        let shared_ubo = game.update(
+        let (width, height) = context.extent();
+
+        if let Some(ref mut recording) = recording {
+            recording.frame(time, (width, height), input.events())?;
+        }
+
+        // Update game and core components
+        let shared_ubo = step(
+            game,
             time,
             delta,
-            metadata,
-            context.swapchain.extent().height(),
-            context.swapchain.extent().width(),
+            timestep,
+            &mut accumulator,
+            &mut metadata,
+            height,
+            width,
+            input,
+            control,
+            profiler,
             entities,
             components,
             debug,
         );
 
-        /* Fixed update loop */
-
-        accumulator += delta as f32;
-
-        while accumulator >= FIXED_DT {
-            game.fixed_update(
-                time,
-                FIXED_DT,
-                metadata,
-                context.swapchain.extent().height(),
-                context.swapchain.extent().width(),
-                entities,
-                components,
-                debug,
-            );
+        // Update renderer and render frame
+        let result = render(context, profiler, components, debug, shared_ubo);
 
-            // Update core components
-            components.rigidbodies.simulate(&mut components.transforms);
-            components.softbodies.simulate(&mut components.transforms);
-
-            accumulator -= FIXED_DT;
-            metadata.fixed_frame += 1;
-        }
-
-        // Update core component
-        components.draws.transfer(
-            &mut components.transforms,
-            &mut components.softbodies,
-        );
-
-        // Update renderer
-        if let Err(e) = context.update(
-            &components.draws.instances,
-            shared_ubo,
-        ) {
-            // Irrecoverable error
-            panic!("{}", e);
-        }
-
-        #[cfg(debug_assertions)] {
-            if let Err(e) = context.update_debug(&debug.lines) {
-                // Irrecoverable error
-                panic!("{}", e);
-            }
-        }
-
-        // Render frame
-        if let Err(e) = context.draw(&components.draws.instances) {
+        if let Err(e) = result {
             // Handle render errors
             if let vd::ErrorKind::ApiCall(result, _) = e.kind {
                 // Rebuild the swapchain if it becomes out of date
@@ -272,7 +611,10 @@ fn begin_update<T>(
                     // Use existing window size
                     if let Some(size) = window.get_inner_size_pixels() {
                         match context.refresh_swapchain(size.0, size.1) {
-                            Ok(()) => continue,
+                            Ok(()) => {
+                                finish_frame(&mut metadata, profiler, false)?;
+                                continue;
+                            },
                             Err(e) => eprintln!("{}", e) // Fall through
                         }
                     } else {
@@ -283,13 +625,12 @@ fn begin_update<T>(
             }
 
             // Irrecoverable error
-            panic!("{}", e);
+            return Err(Error::from(e));
         }
 
-        // Increment frame counter
-        metadata.frame += 1;
+        finish_frame(&mut metadata, profiler, true)?;
 
-        if now.duration_since(last_updated).as_secs() > 0 {
+        if time - last_updated >= 1. {
             // Frames per second
             metadata.fps = metadata.frame - last_frame;
             last_frame = metadata.frame;
@@ -298,7 +639,754 @@ fn begin_update<T>(
                 println!("Frames per second: {}", metadata.fps);
             }
 
-            last_updated = now;
+            last_updated = time;
+        }
+
+        // Wait for the next frame
+        limiter.wait(&mut clock);
+    }
+
+    Ok(())
+}
+
+// Close out the frame and increment the frame counter. Frames dropped
+// while the swapchain is rebuilt still count, so that frame numbers match
+// between a recording and its replay.
+fn finish_frame(
+    metadata: &mut Metadata,
+    profiler: &mut profiler::Profiler,
+    drawn:    bool,
+) -> Result<(), Error> {
+    if drawn {
+        metadata.timings = profiler.end_frame(metadata.frame)?;
+        metadata.profile = profiler.summary();
+    } else {
+        // Not drawn; keep it out of the timings
+        profiler.discard_frame();
+    }
+
+    metadata.frame += 1;
+
+    Ok(())
+}
+
+// Submit frame data to the renderer
+#[allow(unused_variables)]
+fn render<R>(
+    renderer:   &mut R,
+    profiler:   &mut profiler::Profiler,
+    components: &components::Container,
+    debug:      &debug::Handler,
+    shared_ubo: render::SharedUBO,
+) -> vd::Result<()>
+where
+    R: render::Renderer
+{
+    profiler.time(profiler::Phase::RenderUpdate, || -> vd::Result<()> {
+        renderer.update(&components.draws.instances, shared_ubo)?;
+
+        #[cfg(debug_assertions)] {
+            renderer.update_debug(&debug.lines)?;
+        }
+
+        Ok(())
+    })?;
+
+    profiler.time(profiler::Phase::RenderDraw, || {
+        renderer.draw(&components.draws.instances)
+    })
+}
+
+// Run game logic and physics for a single frame
+fn step<T>(
+    game:          &mut T,
+    time:          f64,
+    delta:         f64,
+    timestep:      Timestep,
+    accumulator:   &mut f32,
+    metadata:      &mut Metadata,
+    screen_height: u32,
+    screen_width:  u32,
+    input:         &mut input::Manager,
+    control:       &mut control::Handler,
+    profiler:      &mut profiler::Profiler,
+    entities:      &mut entity::Manager,
+    components:    &mut components::Container,
+    debug:         &mut debug::Handler,
+) -> render::SharedUBO
+where
+    T: Start + Update + FixedUpdate
+{
+    /* Fixed timestep accounting */
+
+    let fixed_delta = timestep.delta();
+    let mut substeps = 0;
+
+    if control.paused() {
+        // Run requested steps only, and draw the latest fixed state
+        substeps = control.take_steps(timestep.max_substeps);
+        metadata.alpha = 1.;
+    } else {
+        *accumulator += delta as f32 * control.time_scale();
+
+        // Count this frame's fixed updates ahead of time,
+        // so that the interpolation factor is known to the game update
+        while *accumulator >= fixed_delta {
+            // Drop the remaining steps if the simulation can't keep up
+            if substeps == timestep.max_substeps {
+                *accumulator %= fixed_delta;
+                break;
+            }
+
+            *accumulator -= fixed_delta;
+            substeps += 1;
+        }
+
+        metadata.alpha = *accumulator / fixed_delta;
+    }
+
+    // Update scene data
+    let shared_ubo = profiler.time(profiler::Phase::Update, || {
+        game.update(
+            time,
+            delta,
+            *metadata,
+            screen_height,
+            screen_width,
+            input,
+            control,
+            entities,
+            components,
+            debug,
+        )
+    });
+
+    /* Fixed update loop */
+
+    input.set_fixed(true);
+
+    for _ in 0..substeps {
+        // Store previous state for interpolation
+        components.transforms.save_fixed();
+
+        profiler.time(profiler::Phase::FixedUpdate, || {
+            game.fixed_update(
+                time,
+                fixed_delta,
+                *metadata,
+                screen_height,
+                screen_width,
+                input,
+                control,
+                entities,
+                components,
+                debug,
+            )
+        });
+
+        input.end_fixed();
+
+        // Update core and custom components
+        profiler.time(profiler::Phase::Custom, || {
+            components.fixed_update(
+                components::Stage::BeforePhysics,
+                entities,
+                fixed_delta,
+            )
+        });
+
+        profiler.time(profiler::Phase::Rigidbodies, || {
+            components.rigidbodies.simulate(
+                &mut components.transforms,
+                fixed_delta,
+            )
+        });
+
+        profiler.time(profiler::Phase::Custom, || {
+            components.fixed_update(
+                components::Stage::AfterRigidbodies,
+                entities,
+                fixed_delta,
+            )
+        });
+
+        profiler.time(profiler::Phase::Softbodies, || {
+            components.softbodies.simulate(&mut components.transforms)
+        });
+
+        profiler.time(profiler::Phase::Custom, || {
+            components.fixed_update(
+                components::Stage::AfterPhysics,
+                entities,
+                fixed_delta,
+            )
+        });
+
+        metadata.fixed_frame += 1;
+    }
+
+    input.set_fixed(false);
+
+    // Sync point for entity changes queued during the callbacks
+    components.apply_commands(entities);
+
+    // Update core component
+    profiler.time(profiler::Phase::Transfer, || {
+        components.draws.transfer(
+            &mut components.transforms,
+            &mut components.softbodies,
+            metadata.alpha,
+        )
+    });
+
+    shared_ubo
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use components::Component;
+
+    struct Faller {
+        object: Option<entity::Handle>,
+        updates: u32,
+        fixed_updates: u32,
+        // Called every update
+        script: Option<fn(Metadata, &input::Manager, &mut control::Handler)>,
+        staged: bool, // Add Heights managers around physics
+        sizes: Vec<(u32, u32)>, // Screen size seen by every update
+        frames: Vec<u32>, // Frame number seen by every update
+    }
+
+    impl Faller {
+        fn new() -> Faller {
+            Faller {
+                object: None,
+                updates: 0,
+                fixed_updates: 0,
+                script: None,
+                staged: false,
+                sizes: Vec::new(),
+                frames: Vec::new(),
+            }
+        }
+    }
+
+    // Records the object's height at one stage of every fixed step
+    struct Heights<S> {
+        object: entity::Handle,
+        samples: Vec<f32>,
+        stage: std::marker::PhantomData<S>,
+    }
+
+    struct Before;
+    struct After;
+
+    impl<S> Heights<S> {
+        fn new(object: entity::Handle) -> Heights<S> {
+            Heights {
+                object,
+                samples: Vec::new(),
+                stage: std::marker::PhantomData,
+            }
+        }
+    }
+
+    impl<S: 'static> Component for Heights<S> {
+        fn register(&mut self, _: entity::Handle) { }
+        fn unregister(&mut self, _: entity::Handle) { }
+        fn has(&self, _: entity::Handle) -> bool { false }
+        fn count(&self) -> usize { 0 }
+    }
+
+    impl<S: 'static> components::Custom for Heights<S> {
+        #[allow(unused_variables)]
+        fn fixed_update(
+            &mut self,
+            entities:   &entity::Manager,
+            components: &mut components::Container,
+            delta:      f32,
+        ) {
+            let position = components.transforms.get_position(self.object);
+            self.samples.push(position.y);
+        }
+    }
+
+    impl Start for Faller {
+        fn start(
+            &mut self,
+            entities:   &mut entity::Manager,
+            components: &mut components::Container,
+        ) {
+            let object = entities.add();
+            components.transforms.register(object);
+            components.draws.register(object, 0);
+            components.softbodies.register(object);
+
+            components.softbodies.init_limb(
+                object,
+                1.,
+                1.,
+                alg::Vec3::one(),
+            );
+
+            // Floor
+            components.softbodies.add_plane(
+                alg::Plane::new(alg::Vec3::up(), 1.),
+            );
+
+            if self.staged {
+                components.add(
+                    Heights::<Before>::new(object),
+                    components::Stage::BeforePhysics,
+                );
+
+                components.add(
+                    Heights::<After>::new(object),
+                    components::Stage::AfterPhysics,
+                );
+            }
+
+            self.object = Some(object);
         }
     }
+
+    impl Update for Faller {
+        #[allow(unused_variables)]
+        fn update(
+            &mut self,
+            time:  f64,
+            delta: f64,
+            metadata: Metadata,
+            screen_height: u32,
+            screen_width:  u32,
+            input: &input::Manager,
+            control: &mut control::Handler,
+            entities:   &mut entity::Manager,
+            components: &mut components::Container,
+            debug: &mut debug::Handler,
+        ) -> render::SharedUBO {
+            assert!(metadata.frame == self.updates);
+            self.updates += 1;
+            self.sizes.push((screen_width, screen_height));
+            self.frames.push(metadata.frame);
+
+            if let Some(script) = self.script {
+                script(metadata, input, control);
+            }
+
+            render::SharedUBO::new(alg::Mat::id(), alg::Mat::id())
+        }
+    }
+
+    impl FixedUpdate for Faller {
+        #[allow(unused_variables)]
+        fn fixed_update(
+            &mut self,
+            time: f64,
+            fixed_delta: f32,
+            metadata: Metadata,
+            screen_height: u32,
+            screen_width: u32,
+            input: &input::Manager,
+            control: &mut control::Handler,
+            entities: &mut entity::Manager,
+            components: &mut components::Container,
+            debug: &mut debug::Handler,
+        ) {
+            assert!(metadata.fixed_frame == self.fixed_updates);
+            self.fixed_updates += 1;
+        }
+    }
+
+    fn models() -> Vec<render::ModelData> {
+        vec![render::ModelData::new(vec![], vec![])]
+    }
+
+    // Run the game without a frame limit
+    fn headless(
+        game:       &mut Faller,
+        frames:     u32,
+        frame_time: f64,
+        timestep:   Timestep,
+    ) -> Headless {
+        go_headless(
+            models(),
+            game,
+            frames,
+            frame_time,
+            timestep,
+            clock::FrameLimit::Unlimited,
+        ).unwrap()
+    }
+
+    #[test]
+    fn headless_steps() {
+        let mut game = Faller::new();
+
+        // One extra frame, since the first has no delta
+        let result = headless(&mut game, 61, 1. / 30., Timestep::default());
+
+        assert!(game.updates == 61);
+        assert!(result.metadata.frame == 61);
+        assert!(result.metadata.fixed_frame == game.fixed_updates);
+
+        // Two seconds of simulated time at 100 Hz
+        eprintln!("Fixed updates: {}", game.fixed_updates);
+        assert!(game.fixed_updates >= 199 && game.fixed_updates <= 200);
+    }
+
+    #[test]
+    fn headless_softbody_rests_on_plane() {
+        let mut game = Faller::new();
+        let result = headless(&mut game, 300, 1. / 60., Timestep::default());
+
+        let position = result.components.transforms.get_position(
+            game.object.unwrap(),
+        );
+
+        eprintln!("Final position: {}", position);
+
+        // Limb has fallen from the origin and settled above the floor
+        assert!(position.y < 0.);
+        assert!(position.y > -1.);
+    }
+
+    #[test]
+    fn headless_frame_limit() {
+        let mut game = Faller::new();
+        let mut result = headless(
+            &mut game,
+            HEADLESS_FRAMES as u32 + 10,
+            1. / 60.,
+            Timestep::default(),
+        );
+
+        let frames = result.renderer.take_frames();
+        assert!(frames.len() == HEADLESS_FRAMES);
+        assert!(result.renderer.frames().next().is_none());
+    }
+
+    #[test]
+    fn headless_records_frames() {
+        let mut game = Faller::new();
+        let result = headless(&mut game, 30, 1. / 60., Timestep::default());
+
+        assert!(result.renderer.frames().len() == 30);
+
+        let frame = result.renderer.last().unwrap();
+        assert!(
+            frame.shared_ubo
+                == render::SharedUBO::new(alg::Mat::id(), alg::Mat::id())
+        );
+
+        // Single instance of the only model
+        assert!(frame.instances.len() == 1);
+        assert!(frame.instances[0].len() == 1);
+
+        let (position, _, _) = result.components.transforms.get_interpolated(
+            game.object.unwrap(),
+            result.metadata.alpha,
+        );
+
+        assert!(
+            frame.instances[0][0].model()
+                == alg::Mat::translation_vec(position)
+        );
+    }
+
+    #[test]
+    fn clamp_substeps() {
+        let mut game = Faller::new();
+
+        // Long frames; each one (after the first, which has no delta) is
+        // worth 50 fixed updates
+        let result = headless(&mut game, 11, 0.5, Timestep::new(100, 4));
+
+        assert!(result.metadata.fixed_frame == 40);
+    }
+
+    #[test]
+    fn fixed_rate() {
+        let mut game = Faller::new();
+
+        // One extra frame, since the first has no delta
+        let result = headless(&mut game, 101, 0.1, Timestep::new(20, 8));
+
+        // Ten seconds at 20 Hz
+        assert!(result.metadata.fixed_frame >= 199);
+        assert!(result.metadata.fixed_frame <= 200);
+    }
+
+    #[test]
+    fn interpolate_draws() {
+        let mut game = Faller::new();
+
+        // Half a fixed step per frame (after the first, which has no delta)
+        let result = headless(&mut game, 22, 0.005, Timestep::new(100, 8));
+
+        let alpha = result.metadata.alpha;
+        eprintln!("Alpha: {}", alpha);
+        assert!(alpha > 0.4 && alpha < 0.6);
+
+        let object = game.object.unwrap();
+        let transforms = &result.components.transforms;
+
+        let (position, _, _) = transforms.get_interpolated(object, alpha);
+        let current = transforms.get_position(object);
+
+        // Falling, so the drawn position lags behind the simulation
+        assert!(position.y > current.y);
+
+        let frame = result.renderer.last().unwrap();
+        assert!(
+            frame.instances[0][0].model()
+                == alg::Mat::translation_vec(position)
+        );
+    }
+
+    #[test]
+    fn limit_headless() {
+        let mut game = Faller::new();
+
+        // Frames are cheap, but paced to 50 per second
+        let result = go_headless(
+            models(),
+            &mut game,
+            151,
+            0.001,
+            Timestep::default(),
+            clock::FrameLimit::Target(50),
+        ).unwrap();
+
+        eprintln!("Frames per second: {}", result.metadata.fps);
+        assert!(result.metadata.fps == 50);
+
+        // Three seconds of simulated time at 100 Hz
+        eprintln!("Fixed updates: {}", game.fixed_updates);
+        assert!(game.fixed_updates >= 299 && game.fixed_updates <= 300);
+    }
+
+    #[test]
+    fn control_exit() {
+        let mut game = Faller::new();
+        game.script = Some(|metadata, _, control| {
+            if metadata.frame == 5 { control.exit(); }
+        });
+
+        let result = headless(&mut game, 100, 0.01, Timestep::default());
+
+        // The requesting frame completes
+        assert!(result.metadata.frame == 6);
+        assert!(result.renderer.frames().len() == 6);
+    }
+
+    #[test]
+    fn control_pause_step() {
+        let mut game = Faller::new();
+        game.script = Some(|metadata, _, control| {
+            match metadata.frame {
+                3 => control.pause(),
+                6 => control.step(),
+                _ => (),
+            }
+        });
+
+        // One fixed step per frame
+        let result = headless(&mut game, 10, 0.125, Timestep::new(8, 8));
+
+        // Frames 1-3, then the single step
+        assert!(result.metadata.fixed_frame == 4);
+        assert!(result.metadata.alpha == 1.);
+    }
+
+    #[test]
+    fn control_time_scale() {
+        let mut game = Faller::new();
+        game.script = Some(|metadata, _, control| {
+            if metadata.frame == 0 { control.set_time_scale(0.5); }
+        });
+
+        // One extra frame, since the first has no delta
+        let result = headless(&mut game, 9, 0.125, Timestep::new(8, 8));
+
+        // Half a fixed step per frame
+        assert!(result.metadata.fixed_frame == 4);
+    }
+
+    #[test]
+    fn profile_phases() {
+        let mut game = Faller::new();
+
+        let result = headless(&mut game, 10, 0.01, Timestep::default());
+
+        let timings = result.metadata.timings;
+        let profile = result.metadata.profile;
+
+        // The softbody solver ran on the last frame
+        assert!(timings.get(profiler::Phase::Softbodies) > 0.);
+        assert!(timings.total() > 0.);
+
+        for phase in &profiler::PHASES {
+            assert!(profile.min.get(*phase) <= profile.avg.get(*phase));
+            assert!(profile.avg.get(*phase) <= profile.max.get(*phase));
+        }
+    }
+
+    #[test]
+    fn custom_stages() {
+        let mut game = Faller::new();
+        game.staged = true;
+
+        let result = headless(&mut game, 10, 0.01, Timestep::default());
+
+        let components = &result.components;
+        let before = &components.get::<Heights<Before>>().unwrap().samples;
+        let after = &components.get::<Heights<After>>().unwrap().samples;
+
+        assert!(before.len() == result.metadata.fixed_frame as usize);
+        assert!(after.len() == before.len());
+
+        // Physics runs in between (the object is falling)...
+        assert!(after[0] < before[0]);
+
+        // ...and nothing else moves the object
+        for (last, next) in after.iter().zip(&before[1..]) {
+            assert!(last == next);
+        }
+    }
+
+    fn replay() -> replay::Replay {
+        let mut replay = replay::Replay::new(Timestep::new(60, 4), (640, 480));
+
+        // Uneven frame times, with a pause key pressed partway through
+        let mut time = 0.;
+
+        for frame in 0..40 {
+            let events = match frame {
+                20 => vec![input::Event::KeyDown(input::Key::P)],
+                21 => vec![input::Event::KeyUp(input::Key::P)],
+                _ => vec![],
+            };
+
+            // The window is resized partway through
+            let resize = if frame == 30 { Some((800, 600)) } else { None };
+
+            replay.frames.push(replay::Frame { time, resize, events });
+            time += 0.01 + 0.007 * (frame % 3) as f64;
+        }
+
+        replay
+    }
+
+    #[test]
+    fn replay_deterministic() {
+        let replay = replay();
+        let text = replay.to_string();
+
+        let mut results = Vec::new();
+
+        for _ in 0..2 {
+            let mut game = Faller::new();
+            game.script = Some(|_, input, control| {
+                if input.key_pressed(input::Key::P) { control.pause(); }
+            });
+
+            let replay: replay::Replay = text.parse().unwrap();
+            let bindings = input::Bindings::new();
+            let result = go_replay(models(), &mut game, &replay, bindings)
+                .unwrap();
+
+            assert!(result.metadata.frame == 40);
+            assert!(paused_after_key(&result, &replay));
+
+            // The game sees the recorded window size
+            assert!(game.sizes[..30].iter().all(|size| *size == (640, 480)));
+            assert!(game.sizes[30..].iter().all(|size| *size == (800, 600)));
+
+            results.push(result);
+        }
+
+        // Every recorded frame matches exactly
+        assert!(results[0].renderer.frames().len() == 40);
+
+        for (a, b) in results[0].renderer.frames()
+            .zip(results[1].renderer.frames())
+        {
+            assert!(a.instances == b.instances);
+        }
+    }
+
+    #[test]
+    fn replay_bindings() {
+        let replay = replay();
+
+        let mut game = Faller::new();
+        game.script = Some(|_, input, control| {
+            if input.action_pressed("pause") { control.pause(); }
+        });
+
+        let mut bindings = input::Bindings::new();
+        bindings.parse("pause", "P").unwrap();
+
+        let result = go_replay(models(), &mut game, &replay, bindings)
+            .unwrap();
+
+        assert!(paused_after_key(&result, &replay));
+    }
+
+    #[test]
+    fn refresh_frame() {
+        let mut metadata = Metadata::new();
+        let mut profiler = profiler::Profiler::default();
+
+        finish_frame(&mut metadata, &mut profiler, true).unwrap();
+        let timings = metadata.timings;
+
+        // Counted, but kept out of the timings
+        finish_frame(&mut metadata, &mut profiler, false).unwrap();
+        assert!(metadata.frame == 2);
+        assert!(metadata.timings == timings);
+    }
+
+    #[test]
+    fn replay_refresh() {
+        let mut replay = replay::Replay::new(Timestep::new(60, 4), (640, 480));
+        let mut recorded = Vec::new();
+
+        // Recorded the way the window loop does, with the swapchain rebuilt
+        // (and not drawn) on frame 10
+        let mut metadata = Metadata::new();
+        let mut profiler = profiler::Profiler::default();
+
+        for frame in 0..20 {
+            let resize = if frame == 11 { Some((800, 600)) } else { None };
+            let time = frame as f64 * 0.01;
+
+            replay.frames.push(replay::Frame { time, resize, events: vec![] });
+            recorded.push(metadata.frame);
+
+            finish_frame(&mut metadata, &mut profiler, frame != 10).unwrap();
+        }
+
+        let mut game = Faller::new();
+        let bindings = input::Bindings::new();
+        let result = go_replay(models(), &mut game, &replay, bindings)
+            .unwrap();
+
+        // Every update sees the frame number it was recorded with
+        assert!(game.frames == recorded);
+        assert!(result.metadata.frame == metadata.frame);
+        assert!(game.sizes[11..].iter().all(|size| *size == (800, 600)));
+    }
+
+    // Fixed updates stop after the pause key
+    fn paused_after_key(result: &Headless, replay: &replay::Replay) -> bool {
+        let elapsed = replay.frames[20].time as f32;
+        let expected = (elapsed * replay.timestep.rate as f32) as u32;
+
+        eprintln!("Fixed frames: {}", result.metadata.fixed_frame);
+        result.metadata.fixed_frame <= expected
+            && result.metadata.fixed_frame + 1 >= expected
+    }
 }
@@ -0,0 +1,305 @@
+use std;
+use config;
+
+use std::io::Write;
+
+use error::Error;
+
+const DEFAULT_WINDOW: usize = 120; // Frames
+const PHASE_COUNT: usize = 8;
+
+// Timed sections of a frame
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    Update,
+    FixedUpdate, // Summed over the frame's fixed steps
+    Rigidbodies,
+    Softbodies,
+    Custom, // User-defined component managers
+    Transfer,
+    RenderUpdate,
+    RenderDraw,
+}
+
+pub const PHASES: [Phase; PHASE_COUNT] = [
+    Phase::Update,
+    Phase::FixedUpdate,
+    Phase::Rigidbodies,
+    Phase::Softbodies,
+    Phase::Custom,
+    Phase::Transfer,
+    Phase::RenderUpdate,
+    Phase::RenderDraw,
+];
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Update => "update",
+            Phase::FixedUpdate => "fixed_update",
+            Phase::Rigidbodies => "rigidbodies",
+            Phase::Softbodies => "softbodies",
+            Phase::Custom => "custom",
+            Phase::Transfer => "transfer",
+            Phase::RenderUpdate => "render_update",
+            Phase::RenderDraw => "render_draw",
+        }
+    }
+}
+
+// Seconds spent in each phase
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timings {
+    phases: [f64; PHASE_COUNT],
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings {
+            phases: [0.; PHASE_COUNT],
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, phase: Phase) -> f64 {
+        self.phases[phase as usize]
+    }
+
+    pub fn total(&self) -> f64 {
+        self.phases.iter().sum()
+    }
+}
+
+impl Default for Timings {
+    fn default() -> Timings {
+        Timings::new()
+    }
+}
+
+// Rolling statistics over the last few frames
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Summary {
+    pub min: Timings,
+    pub avg: Timings,
+    pub max: Timings,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            min: Timings::new(),
+            avg: Timings::new(),
+            max: Timings::new(),
+        }
+    }
+}
+
+impl Default for Summary {
+    fn default() -> Summary {
+        Summary::new()
+    }
+}
+
+pub struct Profiler {
+    window:  usize,
+    history: std::collections::VecDeque<Timings>,
+    current: Timings,
+    csv:     Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl Profiler {
+    pub fn new(window: usize) -> Profiler {
+        assert!(window > 0);
+
+        Profiler {
+            window,
+            history: std::collections::VecDeque::with_capacity(window),
+            current: Timings::new(),
+            csv: None,
+        }
+    }
+
+    // Load the [profiler] section of the engine config
+    pub fn from_config() -> Result<Profiler, Error> {
+        let window = config::get("profiler", "window")?
+            .unwrap_or(DEFAULT_WINDOW);
+
+        if window == 0 {
+            return Err(
+                Error::Config(
+                    "profiler window must be positive".to_string()
+                )
+            );
+        }
+
+        let mut profiler = Profiler::new(window);
+
+        if let Some(path) = config::get::<String>("profiler", "csv")? {
+            profiler.write_csv(&path)?;
+        }
+
+        Ok(profiler)
+    }
+
+    // Write every frame's timings to a new file at the given path
+    pub fn write_csv(&mut self, path: &str) -> Result<(), Error> {
+        let file = std::fs::File::create(path)?;
+        let mut csv = std::io::BufWriter::new(file);
+
+        write!(csv, "frame")?;
+
+        for phase in &PHASES {
+            write!(csv, ",{}", phase.name())?;
+        }
+
+        writeln!(csv)?;
+
+        self.csv = Some(csv);
+        Ok(())
+    }
+
+    // Run and time a phase of the current frame
+    #[inline]
+    pub fn time<F, R>(&mut self, phase: Phase, f: F) -> R
+    where
+        F: FnOnce() -> R
+    {
+        let start = std::time::Instant::now();
+        let result = f();
+        let duration = start.elapsed();
+
+        self.current.phases[phase as usize] += duration.as_secs() as f64
+            + (duration.subsec_nanos() as f64 / 1000000000.);
+
+        result
+    }
+
+    // Finish the current frame and return its timings
+    pub fn end_frame(&mut self, frame: u32) -> Result<Timings, Error> {
+        let timings = self.current;
+        self.current = Timings::new();
+
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(timings);
+
+        if let Some(ref mut csv) = self.csv {
+            write!(csv, "{}", frame)?;
+
+            for phase in &PHASES {
+                write!(csv, ",{:.9}", timings.get(*phase))?;
+            }
+
+            writeln!(csv)?;
+        }
+
+        Ok(timings)
+    }
+
+    // Drop the current frame's timings (e.g. for a frame that wasn't drawn)
+    pub fn discard_frame(&mut self) {
+        self.current = Timings::new();
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::new();
+
+        if self.history.is_empty() {
+            return summary;
+        }
+
+        for i in 0..PHASE_COUNT {
+            let samples = self.history.iter().map(|timings| timings.phases[i]);
+
+            summary.min.phases[i] = samples.clone()
+                .fold(f64::INFINITY, f64::min);
+            summary.max.phases[i] = samples.clone().fold(0., f64::max);
+            summary.avg.phases[i] = samples.sum::<f64>()
+                / self.history.len() as f64;
+        }
+
+        summary
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new(DEFAULT_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use profiler::*;
+
+    #[test]
+    fn rolling_summary() {
+        let mut profiler = Profiler::new(2);
+
+        // Inject timings directly
+        for &seconds in &[4., 1., 3.] {
+            profiler.current.phases[Phase::Softbodies as usize] = seconds;
+            profiler.end_frame(0).unwrap();
+        }
+
+        // The first frame has left the window
+        let summary = profiler.summary();
+        assert!(summary.min.get(Phase::Softbodies) == 1.);
+        assert!(summary.avg.get(Phase::Softbodies) == 2.);
+        assert!(summary.max.get(Phase::Softbodies) == 3.);
+        assert!(summary.max.get(Phase::Update) == 0.);
+    }
+
+    #[test]
+    fn discard_frame() {
+        let mut profiler = Profiler::new(2);
+
+        profiler.current.phases[Phase::Update as usize] = 4.;
+        profiler.discard_frame();
+
+        // Not carried into the next frame
+        let timings = profiler.end_frame(0).unwrap();
+        assert!(timings.get(Phase::Update) == 0.);
+    }
+
+    #[test]
+    fn time_phases() {
+        let mut profiler = Profiler::new(4);
+
+        let value = profiler.time(Phase::Update, || {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            5
+        });
+
+        assert!(value == 5);
+
+        let timings = profiler.end_frame(0).unwrap();
+        assert!(timings.get(Phase::Update) >= 0.002);
+        assert!(timings.get(Phase::Transfer) == 0.);
+        assert!(timings.total() == timings.get(Phase::Update));
+    }
+
+    #[test]
+    fn csv() {
+        let path = std::env::temp_dir().join("nmg_profiler_test.csv");
+        let path = path.to_str().unwrap();
+
+        let mut profiler = Profiler::new(4);
+        profiler.write_csv(path).unwrap();
+        profiler.end_frame(0).unwrap();
+        profiler.end_frame(1).unwrap();
+        drop(profiler); // Flush
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert!(lines.len() == 3);
+        assert!(lines[0].starts_with("frame,update,fixed_update"));
+        assert!(lines[2].starts_with("1,0.000000000"));
+        assert!(lines[2].split(',').count() == PHASES.len() + 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -7,6 +7,9 @@ use graphics;
 use config;
 use statics;
 use util;
+use window;
+
+use error::Error;
 
 macro_rules! offset_of {
     ($struct:ty, $field:tt) => (
@@ -37,6 +40,25 @@ pub const MAX_SOFTBODY_VERT: usize = (
     DYNAMIC_UBO_WIDTH - std::mem::size_of::<alg::Mat>()
 ) / std::mem::size_of::<PaddedVec3>();
 
+// Backend interface driven by the engine loop
+pub trait Renderer {
+    // Width and height of the render target
+    fn extent(&self) -> (u32, u32);
+
+    // Upload per-frame data
+    fn update(
+        &mut self,
+        instances: &Instances,
+        shared_ubo: SharedUBO,
+    ) -> vd::Result<()>;
+
+    #[cfg(debug_assertions)]
+    fn update_debug(&mut self, lines: &[DebugLine]) -> vd::Result<()>;
+
+    // Submit the frame
+    fn draw(&mut self, instances: &Instances) -> vd::Result<()>;
+}
+
 #[allow(dead_code)]
 pub struct Context<'a> {
     pub device:    vd::Device,
@@ -50,6 +72,7 @@ pub struct Context<'a> {
     sharing_mode:   vd::SharingMode,
     q_indices:      Vec<u32>,
     present_mode:   vd::PresentModeKhr,
+    shader_path:    String,
 
     /* Fixed information */
 
@@ -86,6 +109,9 @@ pub struct Context<'a> {
     dyn_ubo_buffer: vd::BufferHandle,
     dyn_ubo_memory: vd::DeviceMemoryHandle,
 
+    // Version of the instances last copied into the dynamic UBO
+    uploaded: Option<u64>,
+
     /* Debug data */
 
     debug_data: Option<DebugData>,
@@ -103,8 +129,9 @@ pub struct Context<'a> {
 impl<'a> Context<'a> {
     pub fn new(
         window:     &vdw::winit::Window,
-        model_data: Vec<ModelData>,
-    ) -> vd::Result<Context> {
+        model_data:   Vec<ModelData>,
+        present_mode: window::PresentMode,
+    ) -> Result<Context, Error> {
         let (
             surface,
             graphics_family,
@@ -118,13 +145,23 @@ impl<'a> Context<'a> {
             transient_pool,
             image_available,
             render_complete,
-        ) = init_vulkan(window)?;
+        ) = init_vulkan(window, present_mode)?;
+
+        let shader_path: String = config::require("settings", "shader_path")?;
 
         let (
             _vert_mod,
             _frag_mod,
             shader_stages,
-        ) = load_shaders(device.clone())?;
+        ) = load_shaders(device.clone(), &shader_path).map_err(|e| {
+            Error::Asset(
+                format!(
+                    "could not load shaders from \"{}\": {}",
+                    shader_path,
+                    e,
+                )
+            )
+        })?;
 
         let (
             vertex_buffer,
@@ -148,10 +185,14 @@ impl<'a> Context<'a> {
             pipeline_layout,
         ) = init_fixed(device.clone())?;
 
+        // Match the window's initial size
+        let (width, height) = window.get_inner_size_pixels()
+            .ok_or_else(|| Error::Window("no window size".to_string()))?;
+
         let (swapchain, command_fences, _views) = init_swapchain(
             &device,
             &surface,
-            1280, 720, // Default
+            width, height,
             &surface_format,
             sharing_mode,
             &q_indices,
@@ -183,7 +224,12 @@ impl<'a> Context<'a> {
             &render_pass,
             &pipeline_layout,
             &device,
-        )?;
+            &shader_path,
+        ).map_err(|e| {
+            Error::Asset(
+                format!("could not load debug shaders: {}", e)
+            )
+        })?;
 
         let debug_line_count = 0;
 
@@ -222,6 +268,7 @@ impl<'a> Context<'a> {
                 sharing_mode,
                 q_indices,
                 present_mode,
+                shader_path,
                 graphics_family,
                 present_family,
                 drawing_pool,
@@ -251,6 +298,7 @@ impl<'a> Context<'a> {
                 ubo_memory,
                 dyn_ubo_buffer,
                 dyn_ubo_memory,
+                uploaded: None,
                 debug_data,
                 debug_line_count,
                 _vert_mod,
@@ -299,6 +347,7 @@ impl<'a> Context<'a> {
             &render_pass,
             &self.pipeline_layout,
             &self.device,
+            &self.shader_path,
         )?;
 
         let (
@@ -351,6 +400,7 @@ impl<'a> Context<'a> {
         self.ubo_memory = ubo_memory;
         self.dyn_ubo_buffer = dyn_ubo_buffer;
         self.dyn_ubo_memory = dyn_ubo_memory;
+        self.uploaded = None; // New buffer is empty
 
         self._depth_image = _depth_image;
         self._views = _views;
@@ -362,9 +412,18 @@ impl<'a> Context<'a> {
 
         Ok(())
     }
+}
+
+impl<'a> Renderer for Context<'a> {
+    fn extent(&self) -> (u32, u32) {
+        (
+            self.swapchain.extent().width(),
+            self.swapchain.extent().height(),
+        )
+    }
 
     #[cfg(debug_assertions)]
-    pub fn update_debug(&mut self, lines: &[DebugLine]) -> vd::Result<()> {
+    fn update_debug(&mut self, lines: &[DebugLine]) -> vd::Result<()> {
         // Update debug line count
         self.debug_line_count = lines.len() as u32;
 
@@ -386,7 +445,7 @@ impl<'a> Context<'a> {
         Ok(())
     }
 
-    pub fn update(
+    fn update(
         &mut self,
         instances: &Instances,
         shared_ubo: SharedUBO,
@@ -407,6 +466,9 @@ impl<'a> Context<'a> {
         // Early exit
         if count == 0 { return Ok(()); }
 
+        // Skip the copy if no instance has changed since the last one
+        if self.uploaded == Some(instances.version()) { return Ok(()); }
+
         // Not optimal: requires copies and a heap allocation
         let mut dynamic_buffer = util::AlignedBuffer::<InstanceUBO>::new(
             self.ubo_alignment as usize,
@@ -428,10 +490,12 @@ impl<'a> Context<'a> {
             )?;
         }
 
+        self.uploaded = Some(instances.version());
+
         Ok(())
     }
 
-    pub fn draw(&self, instances: &Instances) -> vd::Result<()> {
+    fn draw(&mut self, instances: &Instances) -> vd::Result<()> {
         // Note: will most likely return an image index that is still in use
         let index = self.swapchain.acquire_next_image_khr(
             u64::max_value(), // Disable timeout
@@ -660,7 +724,9 @@ impl<'a> Context<'a> {
 
         Ok(())
     }
+}
 
+impl<'a> Context<'a> {
     // Free memory allocated on the GPU at init
     unsafe fn free_device_init(&mut self) {
         // Vertex buffer
@@ -708,6 +774,117 @@ impl<'a> Drop for Context<'a> {
     }
 }
 
+// Data submitted to a renderer for a single frame
+#[derive(Clone)]
+pub struct Frame {
+    pub shared_ubo: SharedUBO,
+    pub instances:  Vec<Vec<InstanceUBO>>, // Indexed by model
+
+    #[cfg(debug_assertions)]
+    pub lines: Vec<DebugLine>,
+}
+
+impl Frame {
+    fn new() -> Frame {
+        Frame {
+            shared_ubo: SharedUBO::new(alg::Mat::id(), alg::Mat::id()),
+            instances: Vec::new(),
+
+            #[cfg(debug_assertions)]
+            lines: Vec::new(),
+        }
+    }
+}
+
+// In-memory renderer; records every frame instead of drawing it
+pub struct Recorder {
+    frames:  std::collections::VecDeque<Frame>,
+    pending: Frame,
+    limit:   Option<usize>, // Oldest frames are dropped past the limit
+    width:   u32,
+    height:  u32,
+}
+
+impl Recorder {
+    pub fn new(width: u32, height: u32) -> Recorder {
+        Recorder {
+            frames: std::collections::VecDeque::new(),
+            pending: Frame::new(),
+            limit: None,
+            width,
+            height,
+        }
+    }
+
+    // Keep only the most recent frames, for long runs
+    pub fn with_limit(mut self, limit: usize) -> Recorder {
+        assert!(limit > 0);
+
+        self.limit = Some(limit);
+        self
+    }
+
+    // Change the size reported through extent()
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    // Recorded frames, oldest first
+    pub fn frames(&self) -> std::collections::vec_deque::Iter<Frame> {
+        self.frames.iter()
+    }
+
+    // Drain the recorded frames, oldest first
+    pub fn take_frames(&mut self) -> Vec<Frame> {
+        std::mem::take(&mut self.frames).into()
+    }
+
+    // Most recently drawn frame
+    pub fn last(&self) -> Option<&Frame> {
+        self.frames.back()
+    }
+}
+
+impl Renderer for Recorder {
+    fn extent(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn update(
+        &mut self,
+        instances: &Instances,
+        shared_ubo: SharedUBO,
+    ) -> vd::Result<()> {
+        self.pending.shared_ubo = shared_ubo;
+        self.pending.instances = instances.data.clone();
+
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    fn update_debug(&mut self, lines: &[DebugLine]) -> vd::Result<()> {
+        self.pending.lines = lines.to_vec();
+
+        Ok(())
+    }
+
+    fn draw(&mut self, instances: &Instances) -> vd::Result<()> {
+        debug_assert!(instances.count() == self.pending.instances.iter()
+            .fold(0, |sum, model| sum + model.len()));
+
+        if let Some(limit) = self.limit {
+            if self.frames.len() == limit {
+                self.frames.pop_front();
+            }
+        }
+
+        self.frames.push_back(self.pending.clone());
+
+        Ok(())
+    }
+}
+
 #[allow(dead_code)]
 struct DebugData {
     buffer:   vd::BufferHandle,
@@ -754,6 +931,7 @@ impl Model {
 
 pub struct Instances {
     data: Vec<Vec<InstanceUBO>>,
+    version: u64, // Incremented on every change
 }
 
 impl Instances {
@@ -776,7 +954,10 @@ impl Instances {
             }
         };
 
-        Instances { data }
+        Instances {
+            data,
+            version: 0,
+        }
     }
 
     // Returns handle to new instance
@@ -786,6 +967,7 @@ impl Instances {
         model_index: usize,
     ) -> InstanceHandle {
         self.data[model_index].push(instance_data);
+        self.version += 1;
 
         InstanceHandle::new(
             model_index as u32,
@@ -805,6 +987,37 @@ impl Instances {
         );
 
         self.data[m][i] = data;
+        self.version += 1;
+    }
+
+    // Remove an instance, moving the model's last instance into its slot.
+    // If an instance was moved, returns its old handle, which must be
+    // replaced by the removed handle.
+    pub fn remove(&mut self, handle: InstanceHandle) -> Option<InstanceHandle> {
+        let (m, i) = (
+            handle.model_index() as usize,
+            handle.instance_index() as usize,
+        );
+
+        let last = self.data[m].len() - 1;
+        self.data[m].swap_remove(i);
+        self.version += 1;
+
+        if i == last {
+            None
+        } else {
+            Some(InstanceHandle::new(m as u32, last as u32))
+        }
+    }
+
+    // Changes whenever instance data changes, so that renderers can skip
+    // uploading unchanged data
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.data.len()
     }
 
     // Count instances (O(model_count))
@@ -819,7 +1032,7 @@ impl Instances {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct InstanceHandle {
     _value: u32,
 }
@@ -834,11 +1047,11 @@ impl InstanceHandle {
         }
     }
 
-    fn model_index(self) -> u16 {
+    pub fn model_index(self) -> u16 {
         (self._value >> 16) as u16
     }
 
-    fn instance_index(self) -> u16 {
+    pub fn instance_index(self) -> u16 {
         self._value as u16
     }
 }
@@ -930,7 +1143,7 @@ impl Default for PaddedVec3 {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct SharedUBO {
     view:       alg::Mat,
@@ -946,7 +1159,7 @@ impl SharedUBO {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct InstanceUBO {
     model: alg::Mat,
@@ -963,6 +1176,10 @@ impl InstanceUBO {
             offsets,
         }
     }
+
+    pub fn model(&self) -> alg::Mat {
+        self.model
+    }
 }
 
 impl Default for InstanceUBO {
@@ -974,7 +1191,10 @@ impl Default for InstanceUBO {
     }
 }
 
-fn init_vulkan(window: &vdw::winit::Window) -> vd::Result<(
+fn init_vulkan(
+    window:    &vdw::winit::Window,
+    requested: window::PresentMode,
+) -> vd::Result<(
     vd::SurfaceKhr,
     u32,
     u32,
@@ -1104,21 +1324,24 @@ fn init_vulkan(window: &vdw::winit::Window) -> vd::Result<(
     };
 
     let present_mode = {
-        // Fall back on FIFO (guaranteed to be supported)
-        let mut mode = vd::PresentModeKhr::FifoKhr;
-
-        for option in present_modes {
-            // Prefer triple buffering
-            if option == vd::PresentModeKhr::MailboxKhr {
-                mode = vd::PresentModeKhr::MailboxKhr;
-                break;
-            // Otherwise, prefer immediate
-            } else if option == vd::PresentModeKhr::ImmediateKhr {
-                mode = vd::PresentModeKhr::ImmediateKhr;
-            }
-        }
+        // Fall back on the other unsynchronized mode, then FIFO
+        // (guaranteed to be supported)
+        let preferences: &[vd::PresentModeKhr] = match requested {
+            window::PresentMode::Fifo => &[],
+            window::PresentMode::Mailbox => &[
+                vd::PresentModeKhr::MailboxKhr,
+                vd::PresentModeKhr::ImmediateKhr,
+            ],
+            window::PresentMode::Immediate => &[
+                vd::PresentModeKhr::ImmediateKhr,
+                vd::PresentModeKhr::MailboxKhr,
+            ],
+        };
 
-        mode
+        preferences.iter()
+            .find(|mode| present_modes.contains(mode))
+            .cloned()
+            .unwrap_or(vd::PresentModeKhr::FifoKhr)
     };
 
     println!("Swapchain present mode: {:?}", present_mode);
@@ -1248,18 +1471,15 @@ fn get_swapchain_details(
     Ok((formats.into_vec(), present_modes.into_vec()))
 }
 
-fn load_shaders<'a>(device: vd::Device) -> vd::Result<(
+fn load_shaders<'a>(
+    device: vd::Device,
+    path:   &str,
+) -> vd::Result<(
     vd::ShaderModule,
     vd::ShaderModule,
     [vd::PipelineShaderStageCreateInfo<'a>; 2],
 )> {
-    let path = {
-        let mut path = config::ENGINE_CONFIG
-            .section(Some("settings")).unwrap()
-            .get("shader_path").unwrap();
-
-        [path, "/"].concat()
-    };
+    let path = [path, "/"].concat();
 
     println!("Loading shaders from \"{}\"", path);
 
@@ -1405,6 +1625,7 @@ fn init_debug(
     render_pass: &vd::RenderPass,
     pipeline_layout: &vd::PipelineLayout,
     device: &vd::Device,
+    shader_path: &str,
 ) -> vd::Result<Option<DebugData>> { Ok(None) }
 
 #[cfg(debug_assertions)]
@@ -1413,6 +1634,7 @@ fn init_debug(
     render_pass: &vd::RenderPass,
     pipeline_layout: &vd::PipelineLayout,
     device: &vd::Device,
+    shader_path: &str,
 ) -> vd::Result<Option<DebugData>> {
     let properties = device.physical_device().memory_properties();
 
@@ -1427,13 +1649,7 @@ fn init_debug(
 
     /* Load debug shaders */
 
-    let path = {
-        let mut path = config::ENGINE_CONFIG
-            .section(Some("settings")).unwrap()
-            .get("shader_path").unwrap();
-
-        [path, "/"].concat()
-    };
+    let path = [shader_path, "/"].concat();
 
     let vert_buffer = vd::util::read_spir_v_file(
         format!("{}{}", path, "debug_vert.spv")
@@ -0,0 +1,409 @@
+use std;
+use alg;
+use input;
+use Timestep;
+
+use std::io::Write;
+
+use error::Error;
+
+const HEADER: &str = "nmg-replay 2";
+
+/* Replays are plain text, one record per line. Times and positions are
+ * stored as raw bits (hex), so that playback is bit-identical. The window
+ * size is stored at the start, and again whenever it changes:
+ *
+ *     nmg-replay 2
+ *     timestep 100 8
+ *     window 1280 720
+ *     frame 3f947ae147ae147b
+ *     resize 800 600
+ *     down Space
+ *     cursor 43200000 42c80000
+ */
+
+// Timing and input for a single frame
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub time: f64, // Clock time at the start of the frame
+    pub resize: Option<(u32, u32)>, // New window size, if it changed
+    pub events: Vec<input::Event>, // In the order they were handled
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub timestep: Timestep,
+    pub size: (u32, u32), // Window size at the first frame
+    pub frames: Vec<Frame>,
+}
+
+impl Replay {
+    pub fn new(timestep: Timestep, size: (u32, u32)) -> Replay {
+        Replay {
+            timestep,
+            size,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Replay, Error> {
+        let contents = std::fs::read_to_string(path)?;
+
+        contents.parse().map_err(|e| {
+            Error::Asset(format!("invalid replay \"{}\": {}", path, e))
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Replay {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(out, "{}", format_header(self.timestep, self.size))?;
+
+        for frame in &self.frames {
+            write!(
+                out,
+                "{}",
+                format_frame(frame.time, frame.resize, &frame.events),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Replay {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Replay, Self::Err> {
+        let mut lines = value.lines()
+            .enumerate()
+            .filter(|&(_, line)| !line.trim().is_empty());
+
+        match lines.next() {
+            Some((_, line)) if line.trim() == HEADER => (),
+            _ => return Err(format!("missing \"{}\" header", HEADER)),
+        }
+
+        let timestep = match lines.next() {
+            Some((i, line)) => parse_timestep(line)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?,
+            None => return Err("missing timestep".to_string()),
+        };
+
+        let size = match lines.next() {
+            Some((i, line)) => parse_window(line)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?,
+            None => return Err("missing window size".to_string()),
+        };
+
+        let mut replay = Replay::new(timestep, size);
+
+        for (i, line) in lines {
+            parse_record(&mut replay, line)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+
+        Ok(replay)
+    }
+}
+
+// Streams frames to a file as they are played
+pub struct Writer {
+    out: std::io::BufWriter<std::fs::File>,
+    size: (u32, u32), // Last written window size
+}
+
+impl Writer {
+    pub fn create(
+        path: &str,
+        timestep: Timestep,
+        size: (u32, u32),
+    ) -> Result<Writer, Error> {
+        let file = std::fs::File::create(path)?;
+        let mut out = std::io::BufWriter::new(file);
+
+        write!(out, "{}", format_header(timestep, size))?;
+
+        Ok(
+            Writer {
+                out,
+                size,
+            }
+        )
+    }
+
+    // The size is written only when it differs from the last frame's
+    pub fn frame(
+        &mut self,
+        time: f64,
+        size: (u32, u32),
+        events: &[input::Event],
+    ) -> Result<(), Error> {
+        let resize = if size != self.size { Some(size) } else { None };
+        self.size = size;
+
+        write!(self.out, "{}", format_frame(time, resize, events))?;
+        Ok(())
+    }
+}
+
+fn format_header(timestep: Timestep, size: (u32, u32)) -> String {
+    format!(
+        "{}\ntimestep {} {}\nwindow {} {}\n",
+        HEADER,
+        timestep.rate,
+        timestep.max_substeps,
+        size.0,
+        size.1,
+    )
+}
+
+fn format_frame(
+    time: f64,
+    resize: Option<(u32, u32)>,
+    events: &[input::Event],
+) -> String {
+    let mut result = format!("frame {:x}\n", time.to_bits());
+
+    if let Some((width, height)) = resize {
+        result.push_str(&format!("resize {} {}\n", width, height));
+    }
+
+    for event in events {
+        let line = match *event {
+            input::Event::KeyDown(key) => {
+                format!("down {}", input::Source::Key(key))
+            },
+
+            input::Event::KeyUp(key) => {
+                format!("up {}", input::Source::Key(key))
+            },
+
+            input::Event::ButtonDown(button) => {
+                format!("down {}", input::Source::Button(button))
+            },
+
+            input::Event::ButtonUp(button) => {
+                format!("up {}", input::Source::Button(button))
+            },
+
+            input::Event::CursorMoved(position) => {
+                format!("cursor {}", format_vec2(position))
+            },
+
+            input::Event::Scroll(delta) => {
+                format!("scroll {}", format_vec2(delta))
+            },
+        };
+
+        result.push_str(&line);
+        result.push('\n');
+    }
+
+    result
+}
+
+fn format_vec2(value: alg::Vec2) -> String {
+    format!("{:x} {:x}", value.x.to_bits(), value.y.to_bits())
+}
+
+fn parse_timestep(line: &str) -> Result<Timestep, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    match fields.as_slice() {
+        ["timestep", rate, max_substeps] => {
+            let rate: u32 = rate.parse().map_err(|_| "invalid rate")?;
+            let max_substeps: u32 = max_substeps.parse()
+                .map_err(|_| "invalid max substeps")?;
+
+            if rate == 0 || max_substeps == 0 {
+                return Err("timestep values must be positive".to_string());
+            }
+
+            Ok(Timestep::new(rate, max_substeps))
+        },
+
+        _ => Err("missing timestep".to_string()),
+    }
+}
+
+fn parse_window(line: &str) -> Result<(u32, u32), String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    match fields.as_slice() {
+        ["window", width, height] => parse_size(width, height),
+        _ => Err("missing window size".to_string()),
+    }
+}
+
+fn parse_size(width: &str, height: &str) -> Result<(u32, u32), String> {
+    let width = width.parse().map_err(|_| "invalid width")?;
+    let height = height.parse().map_err(|_| "invalid height")?;
+
+    Ok((width, height))
+}
+
+fn parse_record(replay: &mut Replay, line: &str) -> Result<(), String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    if let ["frame", time] = fields.as_slice() {
+        let time = u64::from_str_radix(time, 16)
+            .map_err(|_| format!("invalid time \"{}\"", time))?;
+
+        replay.frames.push(
+            Frame {
+                time: f64::from_bits(time),
+                resize: None,
+                events: Vec::new(),
+            }
+        );
+
+        return Ok(());
+    }
+
+    if let ["resize", width, height] = fields.as_slice() {
+        let size = parse_size(width, height)?;
+
+        return match replay.frames.last_mut() {
+            Some(Frame { resize: ref mut resize @ None, .. }) => {
+                *resize = Some(size);
+                Ok(())
+            },
+
+            Some(_) => Err("resized twice in one frame".to_string()),
+            None => Err("resize before the first frame".to_string()),
+        };
+    }
+
+    let event = match fields.as_slice() {
+        ["down", source] => match source.parse()? {
+            input::Source::Key(key) => input::Event::KeyDown(key),
+            input::Source::Button(button) => input::Event::ButtonDown(button),
+        },
+
+        ["up", source] => match source.parse()? {
+            input::Source::Key(key) => input::Event::KeyUp(key),
+            input::Source::Button(button) => input::Event::ButtonUp(button),
+        },
+
+        ["cursor", x, y] => input::Event::CursorMoved(parse_vec2(x, y)?),
+        ["scroll", x, y] => input::Event::Scroll(parse_vec2(x, y)?),
+
+        _ => return Err(format!("unknown record \"{}\"", line.trim())),
+    };
+
+    match replay.frames.last_mut() {
+        Some(frame) => frame.events.push(event),
+        None => return Err("event before the first frame".to_string()),
+    }
+
+    Ok(())
+}
+
+fn parse_vec2(x: &str, y: &str) -> Result<alg::Vec2, String> {
+    let parse = |value: &str| {
+        u32::from_str_radix(value, 16)
+            .map(f32::from_bits)
+            .map_err(|_| format!("invalid value \"{}\"", value))
+    };
+
+    Ok(alg::Vec2::new(parse(x)?, parse(y)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use alg;
+    use input;
+    use replay::*;
+
+    fn example() -> Replay {
+        let mut replay = Replay::new(Timestep::new(60, 4), (1280, 720));
+
+        replay.frames.push(
+            Frame {
+                time: 0.,
+                resize: None,
+                events: vec![],
+            }
+        );
+
+        replay.frames.push(
+            Frame {
+                time: 1. / 60.,
+                resize: Some((800, 600)),
+                events: vec![
+                    input::Event::KeyDown(input::Key::Space),
+                    input::Event::ButtonUp(input::Button::Other(5)),
+                    input::Event::CursorMoved(alg::Vec2::new(0.1, -3.)),
+                    input::Event::Scroll(alg::Vec2::new(0., 1. / 3.)),
+                ],
+            }
+        );
+
+        replay
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = example();
+        let text = replay.to_string();
+        eprintln!("{}", text);
+
+        assert!(text.parse::<Replay>() == Ok(replay));
+    }
+
+    #[test]
+    fn write_file() {
+        let path = std::env::temp_dir().join("nmg_replay_test.txt");
+        let path = path.to_str().unwrap();
+
+        let replay = example();
+
+        {
+            let mut writer = Writer::create(
+                path,
+                replay.timestep,
+                replay.size,
+            ).unwrap();
+
+            let mut size = replay.size;
+
+            for frame in &replay.frames {
+                size = frame.resize.unwrap_or(size);
+                writer.frame(frame.time, size, &frame.events).unwrap();
+            }
+        }
+
+        assert!(Replay::load(path).unwrap() == replay);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |body: &str| {
+            format!("nmg-replay 2\n{}", body).parse::<Replay>()
+        };
+
+        // After a valid header
+        let records = |body: &str| {
+            parse(&format!("timestep 100 8\nwindow 1 1\n{}", body))
+        };
+
+        assert!("".parse::<Replay>().is_err());
+        assert!("nmg-replay 1\ntimestep 100 8\n".parse::<Replay>().is_err());
+        assert!(parse("timestep 0 8\nwindow 1 1\n").is_err());
+        assert!(parse("timestep 100 8\nframe 0\n").is_err());
+        assert!(parse("timestep 100 8\nwindow 1\n").is_err());
+        assert!(records("down Space\n").is_err());
+        assert!(records("frame 0\njump\n").is_err());
+        assert!(records("resize 2 2\n").is_err());
+        assert!(records("frame 0\nresize 2 2\nresize 3 3\n").is_err());
+        assert!(records("frame 0\nresize 2 2\n").is_ok());
+        assert!(records("frame 0\n").is_ok());
+    }
+}
@@ -0,0 +1,558 @@
+use std;
+use alg;
+use entity;
+use components;
+
+use std::fmt::Write;
+
+use error::Error;
+use components::softbody::Falloff;
+
+const HEADER: &str = "nmg-scene 1";
+
+/* Scenes are plain text, one record per line. Floats are written in their
+ * shortest exact form, so that a saved scene loads back bit for bit and
+ * simulates exactly as the original would have. Blank lines and lines
+ * starting with # are ignored:
+ *
+ *     nmg-scene 1
+ *
+ *     # Entities
+ *     entity 0 0 alive
+ *     name 0:0 left_arm
+ *
+ *     # Transforms
+ *     transform 0:0 1 2 0 0 0 0 1 1 1 1 1 2 0 0 0 0 1
+ *
+ * Custom component managers are not saved.
+ */
+
+// Magnet falloff functions by name, since function pointers can't be saved
+pub type Falloffs<'a> = [(&'a str, Falloff)];
+
+pub fn save(
+    path:       &str,
+    entities:   &entity::Manager,
+    components: &components::Container,
+    falloffs:   &Falloffs,
+) -> Result<(), Error> {
+    let text = to_string(entities, components, falloffs)?;
+    std::fs::write(path, text)?;
+
+    Ok(())
+}
+
+// Load into a fresh entity manager and container, whose softbody timestep
+// must match the scene's
+pub fn load(
+    path:       &str,
+    entities:   &mut entity::Manager,
+    components: &mut components::Container,
+    falloffs:   &Falloffs,
+) -> Result<(), Error> {
+    let text = std::fs::read_to_string(path)?;
+
+    from_str(&text, entities, components, falloffs).map_err(|e| {
+        Error::Scene(format!("invalid scene \"{}\": {}", path, e))
+    })
+}
+
+pub fn to_string(
+    entities:   &entity::Manager,
+    components: &components::Container,
+    falloffs:   &Falloffs,
+) -> Result<String, Error> {
+    // Queued commands may hold closures, which can't be saved
+    if !components.commands.is_empty() {
+        return Err(
+            Error::Scene(
+                "can't save with commands waiting to be applied".to_string()
+            )
+        );
+    }
+
+    let mut out = Writer::new();
+    out.comment("Entities");
+    entities.save_scene(&mut out)?;
+    out.comment("Transforms");
+    components.transforms.save_scene(&mut out);
+    out.comment("Draws");
+    components.draws.save_scene(&mut out);
+    out.comment("Rigidbodies");
+    components.rigidbodies.save_scene(&mut out);
+    out.comment("Softbodies");
+    components.softbodies.save_scene(&mut out, falloffs)?;
+
+    Ok(out.text)
+}
+
+// Load into a fresh entity manager and container
+pub fn from_str(
+    text:       &str,
+    entities:   &mut entity::Manager,
+    components: &mut components::Container,
+    falloffs:   &Falloffs,
+) -> Result<(), String> {
+    {
+        use components::Component;
+
+        let empty = entities.capacity() == 0
+            && components.transforms.count() == 0
+            && components.draws.count() == 0
+            && components.rigidbodies.count() == 0
+            && components.softbodies.count() == 0;
+
+        if !empty {
+            return Err("scenes must be loaded into empty managers".into());
+        }
+    }
+
+    let mut lines = text.lines()
+        .enumerate()
+        .filter(|&(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        });
+
+    match lines.next() {
+        Some((_, line)) if line.trim() == HEADER => (),
+        _ => return Err(format!("missing \"{}\" header", HEADER)),
+    }
+
+    for (i, line) in lines {
+        load_record(line, entities, components, falloffs)
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+    }
+
+    entities.end_scene()
+}
+
+fn load_record(
+    line:       &str,
+    entities:   &mut entity::Manager,
+    components: &mut components::Container,
+    falloffs:   &Falloffs,
+) -> Result<(), String> {
+    let mut fields = Fields::new(line);
+    let kind = fields.word()?;
+
+    if entities.load_scene(kind, &mut fields)? {
+        return fields.end();
+    }
+
+    // Component records may only refer to live entities
+    fields.entities = Some(entities);
+
+    let handled = components.transforms.load_scene(kind, &mut fields)?
+        || components.draws.load_scene(kind, &mut fields)?
+        || components.rigidbodies.load_scene(kind, &mut fields)?
+        || components.softbodies.load_scene(kind, &mut fields, falloffs)?;
+
+    if !handled {
+        return Err(format!("unknown record \"{}\"", kind));
+    }
+
+    fields.end()
+}
+
+// Collects records while saving
+pub struct Writer {
+    text: String,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer {
+            text: format!("{}\n", HEADER),
+        }
+    }
+
+    pub fn push(&mut self, line: Line) {
+        self.text.push_str(&line.text);
+        self.text.push('\n');
+    }
+
+    fn comment(&mut self, text: &str) {
+        self.text.push_str(&format!("\n# {}\n", text));
+    }
+}
+
+// A record being built, e.g. Line::new("rod").handle(entity).f32(length)
+pub struct Line {
+    text: String,
+}
+
+impl Line {
+    pub fn new(kind: &str) -> Line {
+        Line {
+            text: kind.to_string(),
+        }
+    }
+
+    // Must be non-empty and free of whitespace
+    pub fn word(mut self, value: &str) -> Line {
+        debug_assert!(is_word(value));
+
+        self.text.push(' ');
+        self.text.push_str(value);
+        self
+    }
+
+    pub fn usize(mut self, value: usize) -> Line {
+        write!(self.text, " {}", value).unwrap();
+        self
+    }
+
+    pub fn u32(mut self, value: u32) -> Line {
+        write!(self.text, " {}", value).unwrap();
+        self
+    }
+
+    // Shortest representation that parses back to the same value
+    pub fn f32(mut self, value: f32) -> Line {
+        write!(self.text, " {}", value).unwrap();
+        self
+    }
+
+    pub fn vec3(self, value: alg::Vec3) -> Line {
+        self.f32(value.x).f32(value.y).f32(value.z)
+    }
+
+    pub fn quat(self, value: alg::Quat) -> Line {
+        self.f32(value.x).f32(value.y).f32(value.z).f32(value.w)
+    }
+
+    pub fn handle(mut self, value: entity::Handle) -> Line {
+        write!(self.text, " {}", value).unwrap();
+        self
+    }
+}
+
+// Whether the value can be saved as a single field
+pub fn is_word(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with('#')
+        && !value.contains(char::is_whitespace)
+}
+
+// The fields of a record being loaded
+pub struct Fields<'a> {
+    values: std::str::SplitWhitespace<'a>,
+    entities: Option<&'a entity::Manager>, // Checks handles, if set
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: &'a str) -> Fields<'a> {
+        Fields {
+            values: line.split_whitespace(),
+            entities: None,
+        }
+    }
+
+    pub fn word(&mut self) -> Result<&'a str, String> {
+        self.values.next().ok_or_else(|| "missing field".to_string())
+    }
+
+    pub fn parse<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        let value = self.word()?;
+
+        value.parse()
+            .map_err(|_| format!("invalid value \"{}\"", value))
+    }
+
+    pub fn vec3(&mut self) -> Result<alg::Vec3, String> {
+        Ok(alg::Vec3::new(self.parse()?, self.parse()?, self.parse()?))
+    }
+
+    pub fn quat(&mut self) -> Result<alg::Quat, String> {
+        Ok(
+            alg::Quat::new(
+                self.parse()?,
+                self.parse()?,
+                self.parse()?,
+                self.parse()?,
+            )
+        )
+    }
+
+    pub fn handle(&mut self) -> Result<entity::Handle, String> {
+        let handle = self.parse()?;
+
+        match self.entities {
+            Some(entities) if !entities.check(handle) => {
+                Err(format!("no live entity {}", handle))
+            },
+            _ => Ok(handle),
+        }
+    }
+
+    // Fails on leftover fields
+    fn end(&mut self) -> Result<(), String> {
+        match self.values.next() {
+            Some(value) => Err(format!("unexpected field \"{}\"", value)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std;
+    use alg;
+    use entity;
+    use components::*;
+    use components::fixtures::*;
+    use scene::*;
+
+    fn pull(position: alg::Vec3, target: alg::Vec3) -> alg::Vec3 {
+        position.lerp(target, 0.5)
+    }
+
+    const FALLOFFS: &Falloffs = &[("pull", pull)];
+
+    fn example() -> (entity::Manager, Container) {
+        let mut entities = entity::Manager::new(8);
+        let mut components = container();
+
+        // Leave a hole in the index space
+        let gone = entities.add();
+        let arm = entities.add();
+        let hand = entities.add();
+        let blob = entities.add();
+        let crate_ = entities.add();
+        let lid = entities.add();
+        entities.remove(gone);
+
+        entities.set_name(arm, "arm");
+        entities.set_name(crate_, "crate");
+        entities.tag(hand, "limb");
+        entities.tag(arm, "limb");
+        entities.tag(arm, "left");
+
+        for limb in &[arm, hand] {
+            components.transforms.register(*limb);
+            components.softbodies.register(*limb);
+            components.draws.register(*limb, 0);
+        }
+
+        components.softbodies.init_limb(arm, 2., 1., alg::Vec3::one());
+        components.softbodies.init_limb(hand, 1., 0.5, alg::Vec3::one());
+        components.softbodies.add_joint(
+            arm,
+            hand,
+            (-45., 45.),
+            (-10., 80.),
+            (-30., 30.),
+        );
+
+        components.transforms.register(blob);
+        components.softbodies.register(blob);
+        components.softbodies.init_instance(
+            blob,
+            1.,
+            0.8,
+            &[alg::Vec3::zero(), alg::Vec3::up(), alg::Vec3::right()],
+            &[(0, 1), (1, 2), (2, 0)],
+            &[(1, pull)],
+        );
+
+        components.softbodies.set_magnet(blob, 0, alg::Vec3::new(0., 3., 1.));
+        components.softbodies.set_force(blob, alg::Vec3::new(0.3, 0., 0.));
+        components.softbodies.add_plane(
+            alg::Plane::new(alg::Vec3::up(), 1.)
+        );
+
+        components.transforms.register(crate_);
+        components.rigidbodies.register(crate_);
+        components.rigidbodies.init(crate_, 3., 0.1);
+        components.rigidbodies.set_force(crate_, alg::Vec3::new(1., 2., 0.));
+        components.rigidbodies.set_torque(crate_, alg::Vec3::new(0., 0.7, 0.));
+        components.draws.register(crate_, 1);
+
+        components.transforms.register(lid);
+        components.transforms.set_parent(lid, crate_);
+        components.transforms.translate(lid, alg::Vec3::up());
+        components.draws.register(lid, 1);
+
+        (entities, components)
+    }
+
+    fn step(components: &mut Container) {
+        components.transforms.save_fixed();
+        components.rigidbodies.simulate(&mut components.transforms, 0.01);
+        components.softbodies.simulate(&mut components.transforms);
+    }
+
+    fn reload(text: &str) -> (entity::Manager, Container) {
+        let mut entities = entity::Manager::new(8);
+        let mut components = container();
+        from_str(text, &mut entities, &mut components, FALLOFFS).unwrap();
+
+        (entities, components)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (entities, mut components) = example();
+
+        for _ in 0..10 {
+            step(&mut components);
+        }
+
+        let text = to_string(&entities, &components, FALLOFFS).unwrap();
+        eprintln!("{}", text);
+
+        let (loaded, mut loaded_components) = reload(&text);
+
+        assert!(
+            to_string(&loaded, &loaded_components, FALLOFFS).unwrap() == text
+        );
+
+        assert!(format!("{:?}", loaded) == format!("{:?}", entities));
+        assert!(loaded.tagged("limb") == entities.tagged("limb"));
+
+        // Continues exactly as the original does
+        for _ in 0..50 {
+            step(&mut components);
+            step(&mut loaded_components);
+        }
+
+        assert!(
+            to_string(&loaded, &loaded_components, FALLOFFS).unwrap()
+                == to_string(&entities, &components, FALLOFFS).unwrap()
+        );
+
+        // Recycles the same index
+        let mut entities = entities;
+        let mut loaded = loaded;
+        assert!(loaded.add() == entities.add());
+    }
+
+    #[test]
+    fn write_file() {
+        let path = std::env::temp_dir().join("nmg_scene_test.txt");
+        let path = path.to_str().unwrap();
+
+        let (entities, components) = example();
+        save(path, &entities, &components, FALLOFFS).unwrap();
+
+        let mut loaded = entity::Manager::new(8);
+        let mut loaded_components = container();
+        load(path, &mut loaded, &mut loaded_components, FALLOFFS).unwrap();
+
+        assert!(
+            to_string(&loaded, &loaded_components, FALLOFFS).unwrap()
+                == to_string(&entities, &components, FALLOFFS).unwrap()
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn timestep_mismatch() {
+        let (entities, mut components) = example();
+        components.softbodies.set_timestep(1. / 50.);
+        let text = to_string(&entities, &components, FALLOFFS).unwrap();
+
+        let mut loaded = entity::Manager::new(8);
+        let mut loaded_components = container();
+        assert!(
+            from_str(&text, &mut loaded, &mut loaded_components, FALLOFFS)
+                .is_err()
+        );
+
+        // Loads once the engine runs at the same rate
+        let mut loaded = entity::Manager::new(8);
+        let mut loaded_components = container();
+        loaded_components.softbodies.set_timestep(1. / 50.);
+        assert!(
+            from_str(&text, &mut loaded, &mut loaded_components, FALLOFFS)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn save_errors() {
+        let (mut entities, mut components) = example();
+
+        // Unknown falloff
+        assert!(to_string(&entities, &components, &[]).is_err());
+
+        let crate_ = entities.find("crate").unwrap();
+        entities.set_name(crate_, "wooden crate");
+        assert!(to_string(&entities, &components, FALLOFFS).is_err());
+        entities.set_name(crate_, "crate");
+
+        components.commands.despawn(crate_);
+        assert!(to_string(&entities, &components, FALLOFFS).is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |body: &str| {
+            let mut entities = entity::Manager::new(8);
+            let mut components = container();
+            let text = format!("{}\n{}", HEADER, body);
+
+            from_str(&text, &mut entities, &mut components, FALLOFFS)
+        };
+
+        assert!(parse("").is_ok());
+        assert!(parse("# Comment\n\nentity 0 3 alive").is_ok());
+
+        assert!(parse("entity 1 0 alive").is_err()); // Out of order
+        assert!(parse("entity 0 0 asleep").is_err());
+        assert!(parse("entity 0 0 alive extra").is_err());
+        assert!(parse("entity 0 0 alive\nfree 0").is_err());
+        assert!(parse("entity 0 0 dead\nname 0:0 ghost").is_err());
+        assert!(parse("entity 0 0 alive\nname 0:1 ghost").is_err());
+        assert!(parse("entity 0 0 alive\ntransform 0:0 1 2").is_err());
+        assert!(parse("entity 0 0 alive\ndraw 0:0 2").is_err());
+        assert!(parse("entity 0 0 alive\nrigidbody 0:0 -1 0").is_err());
+        assert!(parse("entity 0 0 alive\n\
+                       transform 0:0 0 0 0 0 0 0 1 1 1 1 0 0 0 0 0 0 1\n\
+                       parent 0:0 0:0").is_err());
+        assert!(parse("entity 0 0 alive\n\
+                       softbody 0:0 none\n\
+                       particle 0:0 0 0 0 0 0 0 0 0 0").is_err());
+        assert!(parse("entity 0 0 alive\n\
+                       softbody 0:0 1 0.5 0 0 0 0 0 0 0 0 0\n\
+                       particle 0:0 0 0 0 0 0 0 0 0 0\n\
+                       magnet 0:0 0 push 0 0 0").is_err());
+        assert!(parse("timestep 0").is_err());
+        assert!(parse("timestep 0.01").is_ok());
+        assert!(parse("timestep 0.02").is_err()); // Not the engine's
+        assert!(parse("entity 0 0 alive\nteleport 0:0").is_err());
+        assert!(parse("entity 0:0").is_err());
+
+        // Components of missing or dead entities
+        assert!(parse("rigidbody 2:0 1 0").is_err());
+        assert!(parse("transform 4000000000:0 0 0 0 0 0 0 1 1 1 1 \
+                       0 0 0 0 0 0 1").is_err());
+        assert!(parse("entity 0 0 dead\nfree 0\ndraw 0:0 0").is_err());
+        assert!(parse("entity 0 0 alive\n\
+                       transform 0:0 0 0 0 0 0 0 1 1 1 1 0 0 0 0 0 0 1\n\
+                       parent 0:0 1:0").is_err());
+
+        // Dead entities must be freed
+        assert!(parse("entity 0 0 dead").is_err());
+        assert!(parse("entity 0 0 dead\nfree 0").is_ok());
+
+        // Missing header
+        {
+            let mut entities = entity::Manager::new(8);
+            let mut components = container();
+            assert!(from_str("", &mut entities, &mut components, &[]).is_err());
+        }
+
+        // Not fresh
+        {
+            let (mut entities, mut components) = example();
+            let text = to_string(&entities, &components, FALLOFFS).unwrap();
+            assert!(
+                from_str(&text, &mut entities, &mut components, FALLOFFS)
+                    .is_err()
+            );
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std;
+use config;
+use statics;
+
+use error::Error;
+
+const DEFAULT_WIDTH: u32 = 1280;
+const DEFAULT_HEIGHT: u32 = 720;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Windowed,
+    Fullscreen, // Exclusive, on the primary monitor
+    Borderless, // Undecorated window covering the primary monitor
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Mode, Self::Err> {
+        match value.trim() {
+            "windowed" => Ok(Mode::Windowed),
+            "fullscreen" => Ok(Mode::Fullscreen),
+            "borderless" => Ok(Mode::Borderless),
+            value => Err(format!("unknown window mode \"{}\"", value)),
+        }
+    }
+}
+
+// Swapchain presentation. Modes the device doesn't support fall back on
+// the closest available one (FIFO is always available).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PresentMode {
+    Fifo,      // Synchronized to the display
+    Mailbox,   // Triple buffered
+    Immediate, // May tear
+}
+
+impl std::str::FromStr for PresentMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<PresentMode, Self::Err> {
+        match value.trim() {
+            "fifo" => Ok(PresentMode::Fifo),
+            "mailbox" => Ok(PresentMode::Mailbox),
+            "immediate" => Ok(PresentMode::Immediate),
+            value => Err(format!("unknown present mode \"{}\"", value)),
+        }
+    }
+}
+
+// Window and display options, loaded from the [window] section of the
+// config and adjusted with the builder methods before starting the engine
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub title:     String,
+    pub width:     u32,
+    pub height:    u32,
+    pub mode:      Mode,
+    pub resizable: bool,
+
+    // Chosen from the frame limit when unset
+    // (FIFO for vsync, mailbox otherwise)
+    pub present_mode: Option<PresentMode>,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            title:     statics::TITLE.to_string(),
+            width:     DEFAULT_WIDTH,
+            height:    DEFAULT_HEIGHT,
+            mode:      Mode::Windowed,
+            resizable: true,
+            present_mode: None,
+        }
+    }
+
+    // Load from the engine config, falling back on the defaults
+    pub fn from_config() -> Result<Settings, Error> {
+        let defaults = Settings::new();
+
+        let width = config::get("window", "width")?
+            .unwrap_or(defaults.width);
+        let height = config::get("window", "height")?
+            .unwrap_or(defaults.height);
+
+        if width == 0 || height == 0 {
+            return Err(
+                Error::Config(
+                    "window width and height must be greater than zero"
+                        .to_string()
+                )
+            );
+        }
+
+        Ok(
+            Settings {
+                title: config::get("window", "title")?
+                    .unwrap_or(defaults.title),
+                width,
+                height,
+                mode: config::get("window", "mode")?
+                    .unwrap_or(defaults.mode),
+                resizable: config::get("window", "resizable")?
+                    .unwrap_or(defaults.resizable),
+                present_mode: config::get("window", "present_mode")?,
+            }
+        )
+    }
+
+    pub fn title(mut self, title: &str) -> Settings {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Settings {
+        assert!(width > 0 && height > 0);
+
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Settings {
+        self.mode = mode;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Settings {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Settings {
+        self.present_mode = Some(present_mode);
+        self
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use window::*;
+
+    #[test]
+    fn parse_modes() {
+        assert!("borderless".parse::<Mode>() == Ok(Mode::Borderless));
+        assert!("mailbox".parse::<PresentMode>() == Ok(PresentMode::Mailbox));
+        assert!("vsync".parse::<PresentMode>().is_err());
+    }
+
+    #[test]
+    fn build() {
+        let settings = Settings::new()
+            .title("Test")
+            .size(640, 480)
+            .mode(Mode::Fullscreen)
+            .resizable(false)
+            .present_mode(PresentMode::Immediate);
+
+        assert!(settings.title == "Test");
+        assert!(settings.width == 640 && settings.height == 480);
+        assert!(settings.mode == Mode::Fullscreen);
+        assert!(!settings.resizable);
+        assert!(settings.present_mode == Some(PresentMode::Immediate));
+    }
+}